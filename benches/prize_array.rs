@@ -0,0 +1,20 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use rand::{rngs::SmallRng, SeedableRng};
+use uma_bingo::{run_sim_const, run_sim_with_prizes, PityMode, NUM_PRIZES, PITY_THRESHOLD};
+
+fn prize_array(c: &mut Criterion) {
+    let mut group = c.benchmark_group("prize_array");
+    let mut rng = SmallRng::from_os_rng();
+
+    group.bench_function("const", |b| {
+        b.iter(|| run_sim_const::<NUM_PRIZES, _>(PityMode::Hard, &mut rng))
+    });
+    group.bench_function("vec", |b| {
+        b.iter(|| run_sim_with_prizes(NUM_PRIZES, PityMode::Hard, PITY_THRESHOLD, &mut rng))
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, prize_array);
+criterion_main!(benches);
@@ -0,0 +1,25 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use rand::{
+    rngs::{SmallRng, StdRng},
+    SeedableRng,
+};
+use uma_bingo::{run_sim, SimConfig};
+
+fn rng_throughput(c: &mut Criterion) {
+    let mut group = c.benchmark_group("run_sim");
+    let config = SimConfig::default();
+
+    let mut small = SmallRng::from_os_rng();
+    group.bench_function("small", |b| b.iter(|| run_sim(&config, &mut small)));
+
+    let mut std = StdRng::from_os_rng();
+    group.bench_function("std", |b| b.iter(|| run_sim(&config, &mut std)));
+
+    let mut thread = rand::rng();
+    group.bench_function("thread", |b| b.iter(|| run_sim(&config, &mut thread)));
+
+    group.finish();
+}
+
+criterion_group!(benches, rng_throughput);
+criterion_main!(benches);
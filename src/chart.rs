@@ -0,0 +1,775 @@
+//! Drawing the results with plotters, into any drawing area the caller owns
+
+use std::{borrow::Cow, collections::HashMap, fmt, ops::Range, str::FromStr};
+
+use plotters::{
+    coord::{
+        ranged1d::{SegmentedCoord, ValueFormatter},
+        types::RangedCoordusize,
+        Shift,
+    },
+    prelude::*,
+};
+
+use crate::{simulator::NamedHistogram, CostModel, StatsSummary, NUM_PRIZES};
+
+/// The most x-axis labels that comfortably fit side by side on a chart
+const MAX_X_LABELS: usize = 15;
+
+/// How far the x-axis labels of the histogram are rotated
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LabelRotation {
+    #[default]
+    None,
+    /// 90 degrees clockwise
+    Quarter,
+    /// 270 degrees clockwise
+    ThreeQuarters,
+}
+
+impl fmt::Display for LabelRotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LabelRotation::None => write!(f, "0"),
+            LabelRotation::Quarter => write!(f, "90"),
+            LabelRotation::ThreeQuarters => write!(f, "270"),
+        }
+    }
+}
+
+impl FromStr for LabelRotation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "0" => Ok(LabelRotation::None),
+            "90" => Ok(LabelRotation::Quarter),
+            "270" => Ok(LabelRotation::ThreeQuarters),
+            _ => Err(format!(
+                "unsupported label rotation '{}', expected 0, 90 or 270",
+                s
+            )),
+        }
+    }
+}
+
+/// Layout options for the histogram chart
+#[derive(Clone, Debug, Default)]
+pub struct HistogramOptions {
+    /// Label every n-th roll count, picked from the axis width when `None`
+    pub label_stride: Option<usize>,
+    pub label_rotation: LabelRotation,
+    /// Put every roll count at or above this into one overflow bucket
+    pub tail_cap: Option<usize>,
+    /// Use a logarithmic y-axis, so that buckets with only a few runs stay visible
+    pub log_y: bool,
+    /// The fewest rolls a run can take, where the x-axis starts. `NUM_PRIZES` when `None`
+    pub min_rolls: Option<usize>,
+    /// The event named in the caption, "uma-bingo" when `None`
+    pub event_name: Option<String>,
+    /// Label every number of rolls with what that many rolls cost as well
+    pub cost: Option<CostModel>,
+}
+
+/// Draw the histogram of how many rolls each run took to earn every prize into `area`
+///
+/// `hist_data` maps numbers of rolls to how many runs took that many. The chart fills the whole
+/// area, so split it first to put the histogram next to other charts:
+///
+/// ```
+/// use std::collections::HashMap;
+///
+/// use plotters::prelude::*;
+/// use uma_bingo::chart::{draw_histogram_into, HistogramOptions};
+///
+/// let hist_data = HashMap::from([(8, 3), (12, 40), (20, 98), (26, 120)]);
+/// let mut buffer = vec![0; 1280 * 360 * 3];
+/// {
+///     let root = BitMapBackend::with_buffer(&mut buffer, (1280, 360)).into_drawing_area();
+///     root.fill(&WHITE)?;
+///     let (left, right) = root.split_horizontally(640);
+///
+///     draw_histogram_into(&left, &hist_data, &HistogramOptions::default())?;
+///     ChartBuilder::on(&right)
+///         .caption("Something else", ("sans-serif", 24))
+///         .build_cartesian_2d(0..10, 0..10)?
+///         .draw_series(LineSeries::new((0..10).map(|x| (x, x)), &RED))?;
+///
+///     root.present()?;
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+///
+/// # Panics
+///
+/// Panics if the histogram is empty.
+pub fn draw_histogram_into<DB: DrawingBackend>(
+    area: &DrawingArea<DB, Shift>,
+    hist_data: &HashMap<usize, usize>,
+    options: &HistogramOptions,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    let runs: usize = hist_data.values().sum();
+    let min_rolls = options.min_rolls.unwrap_or(NUM_PRIZES);
+    let (hist_data, max_rolls) = match options.tail_cap {
+        // A cap at or below the fewest rolls would leave no room for the x-axis
+        Some(tail_cap) => (
+            Cow::Owned(cap_tail(hist_data, tail_cap)),
+            tail_cap.max(min_rolls + 1),
+        ),
+        None => (
+            Cow::Borrowed(hist_data),
+            hist_data.keys().copied().max().unwrap().max(min_rolls + 27),
+        ),
+    };
+    let max_count = *hist_data.values().max().unwrap();
+    let left_label_area_size = if max_count > 500 { 100 } else { 50 };
+    let label_stride = options.label_stride.unwrap_or_else(|| {
+        // The costs make every label about twice as wide
+        let max_labels = if options.cost.is_some() {
+            MAX_X_LABELS / 2
+        } else {
+            MAX_X_LABELS
+        };
+        auto_label_stride(max_rolls - min_rolls + 1, max_labels)
+    });
+
+    let (x_label_transform, bottom_label_area_size) = match options.label_rotation {
+        LabelRotation::None => (FontTransform::None, 50),
+        LabelRotation::Quarter => (FontTransform::Rotate90, 80),
+        LabelRotation::ThreeQuarters => (FontTransform::Rotate270, 80),
+    };
+
+    let mut chart_builder = ChartBuilder::on(area);
+    chart_builder
+        .margin(5)
+        .set_label_area_size(LabelAreaPosition::Bottom, bottom_label_area_size)
+        .set_label_area_size(LabelAreaPosition::Left, left_label_area_size)
+        .caption(
+            format!(
+                "Result of {} simulations of {}",
+                runs,
+                options.event_name.as_deref().unwrap_or("uma-bingo")
+            ),
+            ("Calibri", 36),
+        );
+
+    let x_range = (min_rolls..max_rolls).into_segmented();
+    if options.log_y {
+        // Leave room below 1 so that buckets with a single run still get a visible bar
+        let y_range = (0.5..max_count as f64 * 2.).log_scale();
+        draw_bars(
+            chart_builder.build_cartesian_2d(x_range, y_range)?,
+            &hist_data,
+            0.5,
+            "Number of samples (log scale)",
+            x_label_transform,
+            label_stride,
+            options,
+            min_rolls..max_rolls,
+        )
+    } else {
+        let y_range = 0.0..max_count as f64 + 5.;
+        draw_bars(
+            chart_builder.build_cartesian_2d(x_range, y_range)?,
+            &hist_data,
+            0.,
+            "Number of samples",
+            x_label_transform,
+            label_stride,
+            options,
+            min_rolls..max_rolls,
+        )
+    }
+}
+
+/// Draw the axes and the bars of the histogram on a chart with either a linear or a logarithmic
+/// y-axis, with the bars rising from `baseline`
+#[allow(clippy::too_many_arguments)]
+fn draw_bars<DB, Y>(
+    mut chart_context: ChartContext<DB, Cartesian2d<SegmentedCoord<RangedCoordusize>, Y>>,
+    hist_data: &HashMap<usize, usize>,
+    baseline: f64,
+    y_desc: &str,
+    x_label_transform: FontTransform,
+    label_stride: usize,
+    options: &HistogramOptions,
+    rolls: Range<usize>,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB: DrawingBackend,
+    DB::ErrorType: 'static,
+    Y: Ranged<ValueType = f64> + ValueFormatter<f64>,
+{
+    let min_rolls = rolls.start;
+    let with_cost = |label: String, rolls: usize| match &options.cost {
+        Some(cost) => format!("{} ({})", label, cost.cost(rolls)),
+        None => label,
+    };
+    chart_context
+        .configure_mesh()
+        .label_style(("Calibri", 28))
+        .x_label_style(("Calibri", 28).into_font().transform(x_label_transform))
+        .axis_desc_style(("Calibri", 28))
+        // Ask for a label on every segment and blank out the ones off the stride
+        .x_labels(rolls.len() + 1)
+        .x_label_formatter(&|rolls| match rolls {
+            SegmentValue::CenterOf(rolls) if options.tail_cap == Some(*rolls) => {
+                with_cost(format!(">={}", rolls), *rolls)
+            }
+            SegmentValue::CenterOf(rolls) if (rolls - min_rolls).is_multiple_of(label_stride) => {
+                with_cost(rolls.to_string(), *rolls)
+            }
+            _ => String::new(),
+        })
+        .y_label_formatter(&|count| format!("{:.0}", count))
+        .x_desc(if options.cost.is_some() {
+            "Number of rolls to earn all prizes (currency spent)"
+        } else {
+            "Number of rolls to earn all prizes"
+        })
+        .y_desc(y_desc)
+        .draw()?;
+
+    chart_context.draw_series(
+        Histogram::vertical(&chart_context)
+            .style(BLUE.filled())
+            .margin(10)
+            .baseline(baseline)
+            .data(
+                hist_data
+                    .iter()
+                    .filter(|(_, &count)| count > 0)
+                    .map(|(&rolls, &count)| (rolls, count as f64)),
+            ),
+    )?;
+
+    Ok(())
+}
+
+/// Draw each named histogram as a line in its own color, with the names in the legend
+pub fn draw_overlaid_histograms<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    histograms: &[NamedHistogram],
+    caption: &str,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    let all_counts = || {
+        histograms
+            .iter()
+            .flat_map(|(_, hist_data)| hist_data.iter())
+    };
+    let min_rolls = all_counts()
+        .map(|(&rolls, _)| rolls)
+        .min()
+        .unwrap_or(NUM_PRIZES);
+    let max_rolls = all_counts().map(|(&rolls, _)| rolls).max().unwrap_or(35);
+    let max_count = all_counts().map(|(_, &count)| count).max().unwrap_or(0);
+    let left_label_area_size = if max_count > 500 { 100 } else { 50 };
+
+    let mut chart_context = ChartBuilder::on(root)
+        .margin(5)
+        .set_label_area_size(LabelAreaPosition::Bottom, 50)
+        .set_label_area_size(LabelAreaPosition::Left, left_label_area_size)
+        .caption(caption, ("Calibri", 36))
+        .build_cartesian_2d(min_rolls..max_rolls, 0..max_count + max_count / 20 + 5)?;
+    chart_context
+        .configure_mesh()
+        .label_style(("Calibri", 28))
+        .x_desc("Number of rolls to earn all prizes")
+        .y_desc("Number of samples")
+        .draw()?;
+
+    for (histogram_idx, (name, hist_data)) in histograms.iter().enumerate() {
+        let color = Palette99::pick(histogram_idx);
+        chart_context
+            .draw_series(LineSeries::new(
+                (min_rolls..=max_rolls).map(|rolls| (rolls, *hist_data.get(&rolls).unwrap_or(&0))),
+                color.stroke_width(3),
+            ))?
+            .label(name)
+            .legend(move |(x, y)| PathElement::new([(x, y), (x + 20, y)], color.stroke_width(3)));
+    }
+
+    chart_context
+        .configure_series_labels()
+        .label_font(("Calibri", 28))
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+
+    Ok(())
+}
+
+/// Draw the histogram of each seed as a thin line with their average as a bold line on top
+pub fn draw_seed_comparison<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    histograms: &[HashMap<usize, usize>],
+    runs: usize,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    let min_rolls = histograms
+        .iter()
+        .flat_map(|hist_data| hist_data.keys())
+        .copied()
+        .min()
+        .unwrap_or(NUM_PRIZES);
+    let max_rolls = histograms
+        .iter()
+        .flat_map(|hist_data| hist_data.keys())
+        .copied()
+        .max()
+        .unwrap_or(35);
+    let max_count = histograms
+        .iter()
+        .flat_map(|hist_data| hist_data.values())
+        .copied()
+        .max()
+        .unwrap_or(0);
+    let left_label_area_size = if max_count > 500 { 100 } else { 50 };
+
+    let mut chart_context = ChartBuilder::on(root)
+        .margin(5)
+        .set_label_area_size(LabelAreaPosition::Bottom, 50)
+        .set_label_area_size(LabelAreaPosition::Left, left_label_area_size)
+        .caption(
+            format!(
+                "{} simulations of uma-bingo with {} different seeds",
+                runs,
+                histograms.len()
+            ),
+            ("Calibri", 36),
+        )
+        .build_cartesian_2d(min_rolls..max_rolls, 0f64..max_count as f64 * 1.05 + 5.)?;
+    chart_context
+        .configure_mesh()
+        .label_style(("Calibri", 28))
+        .x_desc("Number of rolls to earn all prizes")
+        .y_desc("Number of samples")
+        .draw()?;
+
+    for (seed_idx, hist_data) in histograms.iter().enumerate() {
+        let series = chart_context.draw_series(LineSeries::new(
+            (min_rolls..=max_rolls)
+                .map(|rolls| (rolls, *hist_data.get(&rolls).unwrap_or(&0) as f64)),
+            Palette99::pick(seed_idx).mix(0.6).stroke_width(1),
+        ))?;
+        if seed_idx == 0 {
+            series
+                .label("Individual seeds")
+                .legend(|(x, y)| PathElement::new([(x, y), (x + 20, y)], Palette99::pick(0)));
+        }
+    }
+
+    chart_context
+        .draw_series(LineSeries::new(
+            (min_rolls..=max_rolls).map(|rolls| {
+                let total: usize = histograms
+                    .iter()
+                    .map(|hist_data| *hist_data.get(&rolls).unwrap_or(&0))
+                    .sum();
+                (rolls, total as f64 / histograms.len() as f64)
+            }),
+            BLACK.stroke_width(3),
+        ))?
+        .label("Average")
+        .legend(|(x, y)| PathElement::new([(x, y), (x + 20, y)], BLACK.stroke_width(3)));
+
+    chart_context
+        .configure_series_labels()
+        .label_font(("Calibri", 28))
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+
+    Ok(())
+}
+
+/// Draw the mean number of rolls of each prize count as a point with its 95% confidence interval
+/// as an error bar
+pub fn draw_summary_chart<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    scenarios: &[(usize, StatsSummary)],
+    runs: usize,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    let intervals: Vec<(f64, f64)> = scenarios
+        .iter()
+        .map(|(_, summary)| summary.mean_confidence_interval())
+        .collect();
+    let min_mean = intervals
+        .iter()
+        .map(|&(low, _)| low)
+        .fold(f64::INFINITY, f64::min);
+    let max_mean = intervals
+        .iter()
+        .map(|&(_, high)| high)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let padding = (max_mean - min_mean).max(1.) / 20.;
+    let max_prizes = scenarios
+        .iter()
+        .map(|&(num_prizes, _)| num_prizes)
+        .max()
+        .unwrap_or(NUM_PRIZES);
+
+    let mut chart_context = ChartBuilder::on(root)
+        .margin(5)
+        .set_label_area_size(LabelAreaPosition::Bottom, 50)
+        .set_label_area_size(LabelAreaPosition::Left, 100)
+        .caption(
+            format!("Mean rolls by prize count ({} simulations each)", runs),
+            ("Calibri", 36),
+        )
+        .build_cartesian_2d(
+            0.5..max_prizes as f64 + 0.5,
+            (min_mean - padding).max(0.)..max_mean + padding,
+        )?;
+    chart_context
+        .configure_mesh()
+        .label_style(("Calibri", 28))
+        .x_labels(max_prizes)
+        .x_label_formatter(&|num_prizes| format!("{:.0}", num_prizes))
+        .x_desc("Number of prizes")
+        .y_desc("Mean number of rolls to earn all prizes")
+        .draw()?;
+
+    chart_context.draw_series(scenarios.iter().zip(&intervals).map(
+        |((num_prizes, summary), &(low, high))| {
+            ErrorBar::new_vertical(
+                *num_prizes as f64,
+                low,
+                summary.mean,
+                high,
+                BLUE.stroke_width(2),
+                12,
+            )
+        },
+    ))?;
+    chart_context.draw_series(scenarios.iter().map(|(num_prizes, summary)| {
+        Circle::new((*num_prizes as f64, summary.mean), 5, BLUE.filled())
+    }))?;
+
+    Ok(())
+}
+
+/// Draw the hazard of every number of rolls as a line with a dot on each number of rolls
+pub fn draw_hazard_chart<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    summary: &StatsSummary,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    let hazard = summary.hazard();
+
+    let mut chart_context = ChartBuilder::on(root)
+        .margin(5)
+        .set_label_area_size(LabelAreaPosition::Bottom, 50)
+        .set_label_area_size(LabelAreaPosition::Left, 100)
+        .caption(
+            format!("Hazard of {} simulations of uma-bingo", summary.runs),
+            ("Calibri", 36),
+        )
+        .build_cartesian_2d(summary.min..summary.max.max(summary.min + 1), 0.0..1.05)?;
+    chart_context
+        .configure_mesh()
+        .label_style(("Calibri", 28))
+        .x_desc("Number of rolls")
+        .y_desc("Chance to finish on this roll")
+        .draw()?;
+
+    chart_context.draw_series(LineSeries::new(
+        hazard.iter().copied(),
+        BLUE.stroke_width(2),
+    ))?;
+    chart_context.draw_series(
+        hazard
+            .iter()
+            .map(|&(rolls, hazard)| Circle::new((rolls, hazard), 4, BLUE.filled())),
+    )?;
+
+    Ok(())
+}
+
+/// Draw the chance of finishing within every number of rolls as a line, with a dashed line at the
+/// number of rolls that `budget` percent of the runs finished within, and what they cost
+pub fn draw_cdf_chart<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    summary: &StatsSummary,
+    budget: f64,
+    cost: Option<&CostModel>,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    let cdf = summary.cdf();
+    let budget_rolls = summary.percentile(budget);
+
+    let mut chart_context = ChartBuilder::on(root)
+        .margin(5)
+        .set_label_area_size(LabelAreaPosition::Bottom, 50)
+        .set_label_area_size(LabelAreaPosition::Left, 100)
+        .caption(
+            format!(
+                "Cumulative distribution of {} simulations of uma-bingo",
+                summary.runs
+            ),
+            ("Calibri", 36),
+        )
+        .build_cartesian_2d(summary.min..summary.max.max(summary.min + 1), 0.0..1.05)?;
+    chart_context
+        .configure_mesh()
+        .label_style(("Calibri", 28))
+        .x_desc("Number of rolls")
+        .y_desc("Chance to have finished")
+        .draw()?;
+
+    chart_context
+        .draw_series(LineSeries::new(cdf.iter().copied(), BLUE.stroke_width(2)))?
+        .label("Finished within this many rolls")
+        .legend(|(x, y)| PathElement::new([(x, y), (x + 20, y)], BLUE.stroke_width(2)));
+    chart_context
+        .draw_series(DashedLineSeries::new(
+            [(budget_rolls, 0.), (budget_rolls, 1.05)],
+            10,
+            6,
+            RED.stroke_width(2),
+        ))?
+        .label(match cost {
+            Some(cost) => format!(
+                "{}% of runs within {} rolls ({} currency)",
+                budget,
+                budget_rolls,
+                cost.cost(budget_rolls)
+            ),
+            None => format!("{}% of runs within {} rolls", budget, budget_rolls),
+        })
+        .legend(|(x, y)| PathElement::new([(x, y), (x + 20, y)], RED.stroke_width(2)));
+    chart_context
+        .configure_series_labels()
+        .position(SeriesLabelPosition::LowerRight)
+        .label_font(("Calibri", 28))
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+
+    Ok(())
+}
+
+/// Draw a heatmap with one row for each of the `prizes` prizes and one column per roll, colored by
+/// how often the prize was first earned on that roll
+pub fn draw_heatmap<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    first_earned: &[Vec<usize>],
+    prizes: usize,
+    runs: usize,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    let max_count = first_earned
+        .iter()
+        .flat_map(|counts| counts.iter())
+        .copied()
+        .max()
+        .unwrap_or(0)
+        .max(1);
+
+    let mut chart_context = ChartBuilder::on(root)
+        .margin(5)
+        .set_label_area_size(LabelAreaPosition::Bottom, 50)
+        .set_label_area_size(LabelAreaPosition::Left, 120)
+        .caption(
+            format!(
+                "Roll on which each prize is first earned ({} simulations)",
+                runs
+            ),
+            ("Calibri", 36),
+        )
+        .build_cartesian_2d(
+            0.5..first_earned.len() as f64 + 0.5,
+            -0.5..prizes as f64 - 0.5,
+        )?;
+    chart_context
+        .configure_mesh()
+        .disable_mesh()
+        .label_style(("Calibri", 28))
+        .y_labels(prizes)
+        .x_label_formatter(&|roll| format!("{:.0}", roll))
+        .y_label_formatter(&|prize_idx| format!("Prize {}", prize_idx + 1.))
+        .x_desc("Roll number")
+        .draw()?;
+
+    chart_context.draw_series(
+        first_earned
+            .iter()
+            .enumerate()
+            .flat_map(|(roll_idx, counts)| {
+                counts.iter().enumerate().map(move |(prize_idx, &count)| {
+                    let (roll, prize) = ((roll_idx + 1) as f64, prize_idx as f64);
+                    Rectangle::new(
+                        [(roll - 0.5, prize - 0.5), (roll + 0.5, prize + 0.5)],
+                        ViridisRGB::get_color(count as f32 / max_count as f32).filled(),
+                    )
+                })
+            }),
+    )?;
+
+    Ok(())
+}
+
+/// Group every number of rolls at or above `tail_cap` into a single overflow bucket at `tail_cap`
+fn cap_tail(hist_data: &HashMap<usize, usize>, tail_cap: usize) -> HashMap<usize, usize> {
+    let mut capped = HashMap::new();
+    for (&rolls, &count) in hist_data {
+        *capped.entry(rolls.min(tail_cap)).or_insert(0) += count;
+    }
+    capped
+}
+
+/// Pick the smallest 1, 2, 5, 10, 20, 50, ... stride that leaves `width` labels at most
+/// `max_labels` of them
+fn auto_label_stride(width: usize, max_labels: usize) -> usize {
+    let mut magnitude = 1;
+    loop {
+        for stride in [magnitude, 2 * magnitude, 5 * magnitude] {
+            if width.div_ceil(stride) <= max_labels {
+                return stride;
+            }
+        }
+        magnitude *= 10;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, path::Path};
+
+    use rand::{rngs::SmallRng, SeedableRng};
+
+    use super::*;
+    use crate::{simulator::Simulator, TEST_SEED};
+
+    /// The histogram of 500 runs on the default rules, seeded so it's the same on every machine
+    fn seeded_histogram() -> HashMap<usize, usize> {
+        let simulator = Simulator::default();
+        let mut rng = SmallRng::seed_from_u64(TEST_SEED);
+        let mut hist_data = HashMap::new();
+        for _ in 0..500 {
+            *hist_data
+                .entry(simulator.run(&mut rng).rolls.len())
+                .or_insert(0) += 1;
+        }
+        hist_data
+    }
+
+    #[test]
+    fn overflow_bucket_counts_every_run_at_or_above_the_cap() {
+        let hist_data = HashMap::from([(8, 3), (12, 5), (20, 4), (21, 2), (35, 1)]);
+        let capped = cap_tail(&hist_data, 20);
+
+        let at_or_above: usize = hist_data
+            .iter()
+            .filter(|(&rolls, _)| rolls >= 20)
+            .map(|(_, &count)| count)
+            .sum();
+        assert_eq!(capped[&20], at_or_above);
+        assert_eq!(capped[&8], 3);
+        assert_eq!(capped[&12], 5);
+        assert_eq!(capped.len(), 3);
+        assert_eq!(capped.values().sum::<usize>(), 15);
+    }
+
+    /// Whether each pixel of `image` is part of a bar, which are the only blue pixels of the chart
+    fn bar_pixels(image: &image::RgbImage) -> Vec<bool> {
+        image
+            .pixels()
+            .map(|&image::Rgb([r, g, b])| b as i32 - (r.max(g) as i32) > 128)
+            .collect()
+    }
+
+    /// Compare the bars of the chart of [`seeded_histogram`] with the ones of the committed
+    /// `tests/golden/histogram.png`
+    ///
+    /// The labels are drawn with whichever system font matches Calibri, so only the blue bars are
+    /// compared, and a few rows of them may differ because the caption's height moves the top of
+    /// the plot. After an intentional change to the chart, look at the new image and regenerate
+    /// the golden file with
+    ///
+    /// ```text
+    /// UPDATE_GOLDEN=1 cargo test --lib histogram_matches_the_golden_image
+    /// ```
+    #[test]
+    fn histogram_matches_the_golden_image() {
+        const SIZE: (u32, u32) = (1280, 720);
+        let golden_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/histogram.png");
+
+        let mut buffer = vec![0; SIZE.0 as usize * SIZE.1 as usize * 3];
+        {
+            let root = BitMapBackend::with_buffer(&mut buffer, SIZE).into_drawing_area();
+            root.fill(&WHITE).unwrap();
+            draw_histogram_into(&root, &seeded_histogram(), &HistogramOptions::default()).unwrap();
+            root.present().unwrap();
+        }
+        let rendered = image::RgbImage::from_raw(SIZE.0, SIZE.1, buffer).unwrap();
+
+        if env::var_os("UPDATE_GOLDEN").is_some() {
+            rendered.save(&golden_path).unwrap();
+            return;
+        }
+        let golden = image::open(&golden_path)
+            .unwrap_or_else(|err| {
+                panic!(
+                    "couldn't open {}, regenerate it with UPDATE_GOLDEN=1: {}",
+                    golden_path.display(),
+                    err
+                )
+            })
+            .into_rgb8();
+        assert_eq!(golden.dimensions(), rendered.dimensions());
+        let golden_bars = bar_pixels(&golden);
+        let rendered_bars = bar_pixels(&rendered);
+        let bar_count = golden_bars.iter().filter(|&&bar| bar).count();
+        let differing = golden_bars
+            .iter()
+            .zip(&rendered_bars)
+            .filter(|(golden, rendered)| golden != rendered)
+            .count();
+        assert!(bar_count > 0);
+        assert!(
+            differing * 50 <= bar_count,
+            "the bars differ from {} in {} of their {} pixels",
+            golden_path.display(),
+            differing,
+            bar_count
+        );
+    }
+
+    #[test]
+    fn log_y_draws_a_skewed_histogram() {
+        // One huge bucket and a long tail of single runs, which a linear axis would flatten
+        let mut hist_data = HashMap::from([(8, 1), (26, 1_000_000)]);
+        hist_data.extend((27..120).map(|rolls| (rolls, 1)));
+        let options = HistogramOptions {
+            log_y: true,
+            ..HistogramOptions::default()
+        };
+
+        const SIZE: (u32, u32) = (640, 360);
+        let mut buffer = vec![0; SIZE.0 as usize * SIZE.1 as usize * 3];
+        let root = BitMapBackend::with_buffer(&mut buffer, SIZE).into_drawing_area();
+        root.fill(&WHITE).unwrap();
+        draw_histogram_into(&root, &hist_data, &options).unwrap();
+        // A single run puts the top of the axis at 2
+        draw_histogram_into(&root, &HashMap::from([(8, 1)]), &options).unwrap();
+        root.present().unwrap();
+    }
+}
@@ -0,0 +1,83 @@
+//! Event definitions read from a TOML or JSON file, for events that don't follow the default
+//! rules of eight equally likely prizes that are each needed once
+//!
+//! An event can have any number of prizes, and a run finishes once it has earned every one of
+//! them. A file looks like
+//!
+//! ```toml
+//! name = "Summer bingo"
+//! prizes = 6
+//! weights = [1, 1, 1, 1, 1, 0.5]
+//! copies = [1, 1, 1, 1, 2, 1]
+//! pity_threshold = 40
+//! ```
+//!
+//! where everything but `prizes` can be left out.
+
+use std::{fs, path::Path};
+
+use serde::Deserialize;
+
+use crate::{simulator::PrizePool, SimConfig};
+
+/// The rules of one event, see the [module docs](self) for the file format
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EventConfig {
+    /// Shown in the chart captions instead of "uma-bingo"
+    pub name: Option<String>,
+    /// How many prizes the event has
+    pub prizes: usize,
+    /// How likely each prize is to be rolled. Every prize is equally likely when this is `None`
+    pub weights: Option<Vec<f64>>,
+    /// How many times each prize has to be rolled before it counts as earned, 1 each when `None`
+    pub copies: Option<Vec<usize>>,
+    /// The pity threshold of the event, unless --pity-threshold picks another one
+    pub pity_threshold: Option<usize>,
+}
+
+impl EventConfig {
+    /// Read an event from `path`, as JSON if it ends in `.json` and as TOML otherwise, and check
+    /// that it fits the simulation
+    pub fn from_path(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| format!("couldn't read {}: {}", path.display(), err))?;
+        let event: EventConfig = if path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            serde_json::from_str(&contents).map_err(|err| err.to_string())?
+        } else {
+            toml::from_str(&contents).map_err(|err| err.to_string())?
+        };
+        event.validate()?;
+        Ok(event)
+    }
+
+    /// Check that the prize count is supported and that the per-prize lists cover every prize
+    pub fn validate(&self) -> Result<(), String> {
+        self.prize_pool().map(|_| ())
+    }
+
+    /// The prizes of the event, with their weights and copies
+    pub fn prize_pool(&self) -> Result<PrizePool, String> {
+        let mut pool = PrizePool::new(self.prizes)?;
+        if let Some(weights) = &self.weights {
+            pool = pool.with_weights(weights)?;
+        }
+        if let Some(copies) = &self.copies {
+            pool = pool.with_copies(copies)?;
+        }
+        Ok(pool)
+    }
+
+    /// Apply the event's rules on top of `config`, which keeps its pity mode, streakiness and the
+    /// other rules an event doesn't define
+    pub fn apply(&self, config: &mut SimConfig) {
+        // okay to unwrap because the event was validated
+        self.prize_pool().unwrap().apply(config);
+        if let Some(pity_threshold) = self.pity_threshold {
+            config.pity_threshold = pity_threshold;
+        }
+    }
+}
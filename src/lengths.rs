@@ -0,0 +1,219 @@
+//! A compact binary file of the number of rolls of every run, which keeps its format version in
+//! the header so that files written by older versions stay readable
+//!
+//! The header is the magic bytes `UMAL`, the format version as a little-endian `u16`, the number
+//! of prizes as a little-endian `u16` and the number of runs as a little-endian `u64`. Every run's
+//! number of rolls follows as a little-endian `u32`.
+
+use std::{
+    fmt,
+    io::{self, Read, Write},
+};
+
+/// The first bytes of every lengths file
+pub const MAGIC: [u8; 4] = *b"UMAL";
+
+/// The format version written by this build, and the newest one it can read. Versions start at 1
+pub const FORMAT_VERSION: u16 = 1;
+
+/// Why a lengths file couldn't be read
+#[derive(Debug)]
+pub enum ReadLengthsError {
+    Io(io::Error),
+    /// The file doesn't start with [`MAGIC`], so it isn't a lengths file at all
+    BadMagic([u8; 4]),
+    /// The file's format version is 0 or newer than this build knows
+    UnsupportedVersion(u16),
+    /// The file is for an event with a different number of prizes
+    PrizeCountMismatch {
+        expected: u16,
+        found: u16,
+    },
+    /// The file ends before all the runs the header promises
+    Truncated {
+        expected: u64,
+        found: u64,
+    },
+}
+
+impl fmt::Display for ReadLengthsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadLengthsError::Io(err) => write!(f, "{}", err),
+            ReadLengthsError::BadMagic(magic) => write!(
+                f,
+                "not a lengths file, it starts with {:02x?} instead of {:02x?}",
+                magic, MAGIC
+            ),
+            ReadLengthsError::UnsupportedVersion(version) => write!(
+                f,
+                "format version {} isn't supported, only versions 1 to {} are",
+                version, FORMAT_VERSION
+            ),
+            ReadLengthsError::PrizeCountMismatch { expected, found } => write!(
+                f,
+                "the file is for {} prizes, but the simulated event has {}",
+                found, expected
+            ),
+            ReadLengthsError::Truncated { expected, found } => write!(
+                f,
+                "the header promises {} runs, but the file ends after {}",
+                expected, found
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ReadLengthsError {}
+
+impl From<io::Error> for ReadLengthsError {
+    fn from(err: io::Error) -> Self {
+        ReadLengthsError::Io(err)
+    }
+}
+
+/// Write the header and then `lengths`, the number of rolls of each run of an event with
+/// `num_prizes` prizes
+pub fn write_lengths(
+    writer: &mut impl Write,
+    num_prizes: usize,
+    lengths: &[u32],
+) -> io::Result<()> {
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+    writer.write_all(&(num_prizes as u16).to_le_bytes())?;
+    writer.write_all(&(lengths.len() as u64).to_le_bytes())?;
+    for length in lengths {
+        writer.write_all(&length.to_le_bytes())?;
+    }
+
+    Ok(())
+}
+
+/// Read the number of rolls of each run back, checking first that the header matches an event with
+/// `num_prizes` prizes
+pub fn read_lengths(
+    reader: &mut impl Read,
+    num_prizes: usize,
+) -> Result<Vec<u32>, ReadLengthsError> {
+    let mut magic = [0; 4];
+    reader
+        .read_exact(&mut magic)
+        .map_err(|err| match err.kind() {
+            // A file too short for the magic can't be a lengths file either
+            io::ErrorKind::UnexpectedEof => ReadLengthsError::BadMagic(magic),
+            _ => ReadLengthsError::Io(err),
+        })?;
+    if magic != MAGIC {
+        return Err(ReadLengthsError::BadMagic(magic));
+    }
+
+    let mut version = [0; 2];
+    reader.read_exact(&mut version)?;
+    let version = u16::from_le_bytes(version);
+    if !(1..=FORMAT_VERSION).contains(&version) {
+        return Err(ReadLengthsError::UnsupportedVersion(version));
+    }
+    let mut found_prizes = [0; 2];
+    reader.read_exact(&mut found_prizes)?;
+    let found_prizes = u16::from_le_bytes(found_prizes);
+    if usize::from(found_prizes) != num_prizes {
+        return Err(ReadLengthsError::PrizeCountMismatch {
+            expected: num_prizes as u16,
+            found: found_prizes,
+        });
+    }
+    let mut runs = [0; 8];
+    reader.read_exact(&mut runs)?;
+    let runs = u64::from_le_bytes(runs);
+
+    // The run count comes from the file, so don't trust it with a huge allocation up front
+    let mut lengths = Vec::new();
+    let mut length = [0; 4];
+    for found in 0..runs {
+        match reader.read_exact(&mut length) {
+            Ok(()) => lengths.push(u32::from_le_bytes(length)),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                return Err(ReadLengthsError::Truncated {
+                    expected: runs,
+                    found,
+                })
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    Ok(lengths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A lengths file of `lengths` for 8 prizes, with its version swapped for `version`
+    fn file_with_version(version: u16, lengths: &[u32]) -> Vec<u8> {
+        let mut file = Vec::new();
+        write_lengths(&mut file, 8, lengths).unwrap();
+        file[4..6].copy_from_slice(&version.to_le_bytes());
+        file
+    }
+
+    #[test]
+    fn lengths_round_trip() {
+        let lengths = [8, 25, 12, 33, 9];
+        let mut file = Vec::new();
+        write_lengths(&mut file, 6, &lengths).unwrap();
+        assert_eq!(file.len(), 4 + 2 + 2 + 8 + 4 * lengths.len());
+        assert_eq!(&file[..4], b"UMAL");
+
+        assert_eq!(read_lengths(&mut file.as_slice(), 6).unwrap(), lengths);
+        let mut empty = Vec::new();
+        write_lengths(&mut empty, 8, &[]).unwrap();
+        assert!(read_lengths(&mut empty.as_slice(), 8).unwrap().is_empty());
+    }
+
+    #[test]
+    fn bad_magic_is_rejected() {
+        let mut file = file_with_version(FORMAT_VERSION, &[8]);
+        file[..4].copy_from_slice(b"PNG\0");
+        assert!(matches!(
+            read_lengths(&mut file.as_slice(), 8),
+            Err(ReadLengthsError::BadMagic(magic)) if magic == *b"PNG\0"
+        ));
+        // Too short to even hold the magic
+        assert!(matches!(
+            read_lengths(&mut b"UM".as_slice(), 8),
+            Err(ReadLengthsError::BadMagic(_))
+        ));
+    }
+
+    #[test]
+    fn unknown_versions_are_rejected() {
+        for version in [0, FORMAT_VERSION + 1, u16::MAX] {
+            let file = file_with_version(version, &[8]);
+            assert!(matches!(
+                read_lengths(&mut file.as_slice(), 8),
+                Err(ReadLengthsError::UnsupportedVersion(found)) if found == version
+            ));
+        }
+    }
+
+    #[test]
+    fn other_prize_counts_and_truncated_files_are_rejected() {
+        let file = file_with_version(FORMAT_VERSION, &[8, 9, 10]);
+        assert!(matches!(
+            read_lengths(&mut file.as_slice(), 6),
+            Err(ReadLengthsError::PrizeCountMismatch {
+                expected: 6,
+                found: 8
+            })
+        ));
+        assert!(matches!(
+            read_lengths(&mut &file[..file.len() - 2], 8),
+            Err(ReadLengthsError::Truncated {
+                expected: 3,
+                found: 2
+            })
+        ));
+    }
+}
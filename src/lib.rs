@@ -0,0 +1,1712 @@
+pub mod chart;
+pub mod event;
+pub mod lengths;
+pub mod simulator;
+pub mod stats;
+
+use std::{fmt, str::FromStr};
+
+use rand::{
+    distr::{weighted::WeightedIndex, Distribution},
+    Rng, RngCore,
+};
+
+pub use stats::{
+    analyze, analyze_histogram, gini_coefficient, ks_test, Autocorrelation, KsTest, Percentile,
+    StatsSummary,
+};
+
+/// How many different prizes the default event has. Events read with [`event::EventConfig`] can
+/// have any other number, which [`SimConfig::prizes`] carries
+pub const NUM_PRIZES: usize = 8;
+
+/// After this many rolls, every roll grants a prize that hasn't been earned yet
+pub const PITY_THRESHOLD: usize = 25;
+
+/// What happens to rolls once the pity threshold has been reached
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PityMode {
+    /// Every roll grants the first prize that hasn't been earned yet
+    #[default]
+    Hard,
+    /// Every roll is drawn uniformly from the prizes that haven't been earned yet
+    Pool,
+    /// There is no pity, rolls keep being drawn normally however long the run takes
+    None,
+}
+
+impl fmt::Display for PityMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PityMode::Hard => write!(f, "hard"),
+            PityMode::Pool => write!(f, "pool"),
+            PityMode::None => write!(f, "none"),
+        }
+    }
+}
+
+impl FromStr for PityMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hard" => Ok(PityMode::Hard),
+            "pool" => Ok(PityMode::Pool),
+            "none" => Ok(PityMode::None),
+            _ => Err(format!(
+                "unknown pity mode '{}', expected hard, pool or none",
+                s
+            )),
+        }
+    }
+}
+
+/// The prize a roll gave, by its index among the event's prizes counting from 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RollResult(usize);
+
+impl From<usize> for RollResult {
+    fn from(prize_idx: usize) -> Self {
+        RollResult(prize_idx)
+    }
+}
+
+impl From<&RollResult> for usize {
+    fn from(value: &RollResult) -> Self {
+        value.0
+    }
+}
+
+/// The rolls of a single run along with how much work it took to simulate
+#[derive(Debug)]
+pub struct RunDetail {
+    pub rolls: Vec<RollResult>,
+    /// How many values were drawn from the RNG. Hard pity grants prizes without drawing, so this
+    /// can be less than the number of rolls, and streakiness draws whether each roll repeats, so
+    /// it can also be more
+    pub rng_draws: usize,
+    /// Whether every prize set was collected, which is only false when the run gave up
+    pub finished: bool,
+    /// How many of the rolls were granted by pity
+    pub pity_rolls: usize,
+}
+
+/// Groups of prizes that each have to be collected in full to complete the bingo
+///
+/// Prizes that aren't in any set never have to be collected.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PrizeSets(Vec<Vec<usize>>);
+
+impl PrizeSets {
+    /// Every prize of the default event in a set of its own, so every prize has to be collected
+    pub fn individual() -> Self {
+        PrizeSets::first(NUM_PRIZES)
+    }
+
+    /// The first `num_prizes` prizes in sets of their own, so only those have to be collected
+    pub fn first(num_prizes: usize) -> Self {
+        PrizeSets((0..num_prizes).map(|prize_idx| vec![prize_idx]).collect())
+    }
+
+    /// Whether every set has been fully collected
+    pub fn is_complete(&self, earned_prizes: &[bool]) -> bool {
+        self.0
+            .iter()
+            .all(|set| set.iter().all(|&prize_idx| earned_prizes[prize_idx]))
+    }
+
+    /// Which of the `prizes` prizes are in at least one set
+    pub fn required(&self, prizes: usize) -> Vec<bool> {
+        let mut required = vec![false; prizes];
+        for &prize_idx in self.0.iter().flatten() {
+            required[prize_idx] = true;
+        }
+        required
+    }
+
+    /// The highest prize index in any of the sets
+    pub fn max_prize(&self) -> Option<usize> {
+        self.0.iter().flatten().copied().max()
+    }
+}
+
+impl Default for PrizeSets {
+    fn default() -> Self {
+        PrizeSets::individual()
+    }
+}
+
+impl FromStr for PrizeSets {
+    type Err = String;
+
+    /// Parse sets of zero-based prize indices, with the indices in a set separated by commas and
+    /// the sets separated by semicolons, e.g. `0,1,2;3,4;5,6,7`. Whether the event has that many
+    /// prizes is up to [`SimConfig::validate`]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(';')
+            .map(|set| {
+                set.split(',')
+                    .map(|prize_idx| {
+                        prize_idx.trim().parse().map_err(|_| {
+                            format!(
+                                "'{}' is not a prize index, expected a whole number from 0",
+                                prize_idx.trim()
+                            )
+                        })
+                    })
+                    .collect()
+            })
+            .collect::<Result<_, _>>()
+            .map(PrizeSets)
+    }
+}
+
+/// A condition on which prizes have been earned, like `prize1 and (prize5 or prize6)`
+///
+/// There is no `not`, so earning another prize can never make a met condition unmet, and the
+/// condition is sure to be met once every prize it mentions has been earned.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Condition {
+    /// The zero-based prize index has been earned
+    Prize(usize),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+impl Condition {
+    /// Whether the condition holds for the prizes earned so far
+    pub fn is_met(&self, earned_prizes: &[bool]) -> bool {
+        match self {
+            Condition::Prize(prize_idx) => earned_prizes[*prize_idx],
+            Condition::And(left, right) => {
+                left.is_met(earned_prizes) && right.is_met(earned_prizes)
+            }
+            Condition::Or(left, right) => left.is_met(earned_prizes) || right.is_met(earned_prizes),
+        }
+    }
+
+    /// Which of the `prizes` prizes the condition mentions
+    pub fn mentioned(&self, prizes: usize) -> Vec<bool> {
+        let mut mentioned = vec![false; prizes];
+        self.mark_mentioned(&mut mentioned);
+        mentioned
+    }
+
+    /// The highest prize index the condition mentions
+    pub fn max_prize(&self) -> usize {
+        match self {
+            Condition::Prize(prize_idx) => *prize_idx,
+            Condition::And(left, right) | Condition::Or(left, right) => {
+                left.max_prize().max(right.max_prize())
+            }
+        }
+    }
+
+    fn mark_mentioned(&self, mentioned: &mut [bool]) {
+        match self {
+            Condition::Prize(prize_idx) => mentioned[*prize_idx] = true,
+            Condition::And(left, right) | Condition::Or(left, right) => {
+                left.mark_mentioned(mentioned);
+                right.mark_mentioned(mentioned);
+            }
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Condition::Prize(prize_idx) => write!(f, "prize{}", prize_idx + 1),
+            Condition::And(left, right) => {
+                // `and` binds tighter than `or`, so only an `or` inside an `and` needs parentheses
+                for (side_idx, side) in [left, right].into_iter().enumerate() {
+                    if side_idx > 0 {
+                        write!(f, " and ")?;
+                    }
+                    match **side {
+                        Condition::Or(..) => write!(f, "({})", side)?,
+                        _ => write!(f, "{}", side)?,
+                    }
+                }
+                Ok(())
+            }
+            Condition::Or(left, right) => write!(f, "{} or {}", left, right),
+        }
+    }
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    /// Parse `prize1`, `prize2` and so on combined with `and`, `or` and parentheses, where `and`
+    /// binds tighter than `or`. Whether the event has that many prizes is up to
+    /// [`SimConfig::validate`]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let spaced = s.replace('(', " ( ").replace(')', " ) ");
+        let tokens: Vec<&str> = spaced.split_whitespace().collect();
+        let mut pos = 0;
+        let condition = parse_or(&tokens, &mut pos)?;
+        match tokens.get(pos) {
+            None => Ok(condition),
+            Some(token) => Err(format!("unexpected '{}' in the condition", token)),
+        }
+    }
+}
+
+/// Parse conditions joined by `or`, starting at `tokens[*pos]`
+fn parse_or(tokens: &[&str], pos: &mut usize) -> Result<Condition, String> {
+    let mut condition = parse_and(tokens, pos)?;
+    while tokens.get(*pos) == Some(&"or") {
+        *pos += 1;
+        condition = Condition::Or(Box::new(condition), Box::new(parse_and(tokens, pos)?));
+    }
+    Ok(condition)
+}
+
+/// Parse conditions joined by `and`, starting at `tokens[*pos]`
+fn parse_and(tokens: &[&str], pos: &mut usize) -> Result<Condition, String> {
+    let mut condition = parse_atom(tokens, pos)?;
+    while tokens.get(*pos) == Some(&"and") {
+        *pos += 1;
+        condition = Condition::And(Box::new(condition), Box::new(parse_atom(tokens, pos)?));
+    }
+    Ok(condition)
+}
+
+/// Parse a single prize or a parenthesized condition at `tokens[*pos]`
+fn parse_atom(tokens: &[&str], pos: &mut usize) -> Result<Condition, String> {
+    let token = *tokens
+        .get(*pos)
+        .ok_or_else(|| "the condition ends too early".to_string())?;
+    *pos += 1;
+    if token == "(" {
+        let condition = parse_or(tokens, pos)?;
+        if tokens.get(*pos) != Some(&")") {
+            return Err("missing ')' in the condition".to_string());
+        }
+        *pos += 1;
+        return Ok(condition);
+    }
+    match token
+        .strip_prefix("prize")
+        .and_then(|number| number.parse::<usize>().ok())
+    {
+        Some(number @ 1..) => Ok(Condition::Prize(number - 1)),
+        _ => Err(format!(
+            "unexpected '{}' in the condition, expected a prize like prize1 or '('",
+            token
+        )),
+    }
+}
+
+/// How likely each prize is to be rolled, relative to each other
+#[derive(Clone, Debug)]
+pub struct PrizeWeights {
+    weights: Vec<f64>,
+    distribution: WeightedIndex<f64>,
+}
+
+impl PrizeWeights {
+    /// One weight for each prize, which have to be non-negative and not all 0
+    pub fn new(weights: Vec<f64>) -> Result<Self, String> {
+        if let Some(weight) = weights
+            .iter()
+            .find(|weight| !weight.is_finite() || **weight < 0.)
+        {
+            return Err(format!(
+                "'{}' is not a weight, expected a non-negative number",
+                weight
+            ));
+        }
+        let distribution = WeightedIndex::new(&weights).map_err(|err| err.to_string())?;
+
+        Ok(PrizeWeights {
+            weights,
+            distribution,
+        })
+    }
+
+    /// The weight of each prize
+    pub fn weights(&self) -> &[f64] {
+        &self.weights
+    }
+
+    /// The prize with the lowest weight, the first one if there is a tie
+    pub fn rarest(&self) -> usize {
+        self.weights
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(prize_idx, _)| prize_idx)
+            .unwrap() // okay to unwrap because WeightedIndex needs at least one weight
+    }
+
+    /// The chance of rolling each prize, i.e. the weights scaled to add up to 1
+    pub fn probabilities(&self) -> Vec<f64> {
+        let total: f64 = self.weights.iter().sum();
+        self.weights.iter().map(|weight| weight / total).collect()
+    }
+
+    /// Roll a prize index according to the weights
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> usize {
+        self.distribution.sample(rng)
+    }
+}
+
+impl FromStr for PrizeWeights {
+    type Err = String;
+
+    /// Parse comma-separated non-negative weights, one for each prize, e.g. `1,1,1,1,1,1,1,0.5`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let weights = s
+            .split(',')
+            .map(|weight| match weight.trim().parse::<f64>() {
+                Ok(weight) if weight.is_finite() && weight >= 0. => Ok(weight),
+                _ => Err(format!(
+                    "'{}' is not a weight, expected a non-negative number",
+                    weight.trim()
+                )),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        PrizeWeights::new(weights)
+    }
+}
+
+/// The rules a simulation is run with
+#[derive(Clone, Debug)]
+pub struct SimConfig {
+    /// How many prizes can be rolled, indexed from 0
+    pub prizes: usize,
+    pub pity_mode: PityMode,
+    /// Every roll after this many rolls is granted by pity, unless the pity mode is `None`
+    pub pity_threshold: usize,
+    /// The sets that all have to be collected to finish a run
+    pub sets: PrizeSets,
+    /// Finish a run once this condition is met instead of once `sets` are collected
+    pub until: Option<Condition>,
+    /// Stop rolling after this many rolls even if the sets aren't complete, like a player who runs
+    /// out of patience
+    pub give_up: Option<usize>,
+    /// How likely each prize is to be rolled, one weight for each prize. Every prize is equally
+    /// likely when this is `None`
+    pub weights: Option<PrizeWeights>,
+    /// Bad luck protection for the rarest prize only: after this many rolls in a row without
+    /// the rarest prize, the next roll grants it. This is separate from the global pity
+    pub blp_threshold: Option<usize>,
+    /// The chance that a roll repeats the previous roll's prize instead of rolling normally, from
+    /// 0 for independent rolls to 1 for always repeating. Pity and bad luck protection still apply
+    pub streakiness: f64,
+    /// Draw the rolls without replacement from a deck of this many tokens instead of rolling
+    /// independently, like the numbers of a real bingo game. Token `t` is prize `t % prizes`, and
+    /// the deck is reshuffled once every token has been drawn
+    pub deck_size: Option<usize>,
+    /// How many times each prize has to be rolled before it counts as earned, one count for each
+    /// prize
+    pub copies: Vec<usize>,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        Self {
+            prizes: NUM_PRIZES,
+            pity_mode: PityMode::default(),
+            pity_threshold: PITY_THRESHOLD,
+            sets: PrizeSets::default(),
+            until: None,
+            give_up: None,
+            weights: None,
+            blp_threshold: None,
+            streakiness: 0.,
+            deck_size: None,
+            copies: vec![1; NUM_PRIZES],
+        }
+    }
+}
+
+impl SimConfig {
+    /// Whether a run that earned `earned_prizes` is over
+    pub fn is_complete(&self, earned_prizes: &[bool]) -> bool {
+        match &self.until {
+            Some(until) => until.is_met(earned_prizes),
+            None => self.sets.is_complete(earned_prizes),
+        }
+    }
+
+    /// The prizes that can help finish a run, which are the ones pity grants
+    pub fn required(&self) -> Vec<bool> {
+        match &self.until {
+            Some(until) => until.mentioned(self.prizes),
+            None => self.sets.required(self.prizes),
+        }
+    }
+
+    /// How many of the prizes are required, see [`SimConfig::required`]
+    pub fn required_count(&self) -> usize {
+        self.required().iter().filter(|&&required| required).count()
+    }
+
+    /// The fewest rolls any run can finish in, which is the cheapest way to meet the completion
+    /// rules counting every copy a prize needs
+    ///
+    /// Finishing the sets takes every required prize, but an `until` condition can be met by
+    /// different groups of the prizes it mentions, so every group of those is tried.
+    pub fn min_rolls(&self) -> usize {
+        let required: Vec<usize> = self
+            .required()
+            .iter()
+            .enumerate()
+            .filter(|(_, &required)| required)
+            .map(|(prize_idx, _)| prize_idx)
+            .collect();
+        if self.until.is_none() {
+            return required
+                .iter()
+                .map(|&prize_idx| self.copies[prize_idx])
+                .sum();
+        }
+
+        let mut earned_prizes = vec![false; self.prizes];
+        (0..1usize << required.len())
+            .filter_map(|mask| {
+                for (bit, &prize_idx) in required.iter().enumerate() {
+                    earned_prizes[prize_idx] = mask & (1 << bit) != 0;
+                }
+                self.is_complete(&earned_prizes).then(|| {
+                    required
+                        .iter()
+                        .filter(|&&prize_idx| earned_prizes[prize_idx])
+                        .map(|&prize_idx| self.copies[prize_idx])
+                        .sum()
+                })
+            })
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Check that the rules fit the prizes and that every run is sure to finish, so that
+    /// simulating can't loop forever
+    ///
+    /// The sets, the condition, the weights and the copies can only refer to the `prizes` there
+    /// are. Pity or a `give_up` roll count always ends a run. Without either, every required prize
+    /// needs a positive weight, unless bad luck protection grants it as the rarest prize, and
+    /// rolls can't be certain to repeat the previous prize. A deck needs a token for every
+    /// required prize.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.prizes == 0 {
+            return Err("there has to be at least one prize".to_string());
+        }
+        let max_prize = match &self.until {
+            Some(until) => Some(until.max_prize()),
+            None => self.sets.max_prize(),
+        };
+        if let Some(max_prize) = max_prize.filter(|&max_prize| max_prize >= self.prizes) {
+            return Err(format!(
+                "prize index {} is needed to finish but there are only {} prizes",
+                max_prize, self.prizes
+            ));
+        }
+        if let Some(weights) = &self.weights {
+            if weights.weights().len() != self.prizes {
+                return Err(format!(
+                    "expected {} weights, one per prize, got {}",
+                    self.prizes,
+                    weights.weights().len()
+                ));
+            }
+        }
+        if self.copies.len() != self.prizes {
+            return Err(format!(
+                "expected {} copy counts, one per prize, got {}",
+                self.prizes,
+                self.copies.len()
+            ));
+        }
+        if self.deck_size == Some(0) {
+            return Err("the deck needs at least one token".to_string());
+        }
+        if let Some(prize_idx) = self.copies.iter().position(|&copies| copies == 0) {
+            return Err(format!(
+                "prize index {} needs at least 1 copy to be earned",
+                prize_idx
+            ));
+        }
+        if self.pity_mode != PityMode::None || self.give_up.is_some() {
+            return Ok(());
+        }
+
+        let required = self.required();
+        if let Some(weights) = &self.weights {
+            let protected = self.blp_threshold.map(|_| weights.rarest());
+            for (prize_idx, &weight) in weights.weights().iter().enumerate() {
+                if required[prize_idx] && weight == 0. && protected != Some(prize_idx) {
+                    return Err(format!(
+                        "prize index {} is needed to finish but has a weight of 0 and there is no pity to grant it",
+                        prize_idx
+                    ));
+                }
+            }
+        }
+        if let Some(deck_size) = self.deck_size {
+            if let Some(prize_idx) = (deck_size..self.prizes).find(|&prize_idx| required[prize_idx])
+            {
+                return Err(format!(
+                    "prize index {} is needed to finish but a deck of {} tokens doesn't have it and there is no pity to grant it",
+                    prize_idx, deck_size
+                ));
+            }
+        }
+        if self.streakiness >= 1. && self.required_count() > 1 {
+            return Err(
+                "with a streakiness of 1 every roll repeats the first one, so without pity the runs can't finish"
+                    .to_string(),
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Keep rolling for prizes until every prize set has been collected, then return the result
+///
+/// If the config has a `give_up` roll count, the rolls stop there and may not collect every set.
+pub fn run_sim<R: Rng + ?Sized>(config: &SimConfig, rng: &mut R) -> Vec<RollResult> {
+    run_sim_detailed(config, rng).rolls
+}
+
+/// Same as [`run_sim`], but also report how many times the RNG was sampled
+pub fn run_sim_detailed<R: Rng + ?Sized>(config: &SimConfig, rng: &mut R) -> RunDetail {
+    run_sim_from(config, &vec![false; config.prizes], 0, rng)
+}
+
+/// Same as [`run_sim_detailed`], but carry on a run that has already made `rolls_so_far` rolls
+/// and earned the prizes in `earned_prizes`, which has one entry per prize. Only the rolls made
+/// from there on are returned
+///
+/// The rolls so far count towards pity and `give_up`. Bad luck protection and streaks start
+/// over, since the earlier rolls themselves aren't known.
+pub fn run_sim_from<R: Rng + ?Sized>(
+    config: &SimConfig,
+    earned_prizes: &[bool],
+    rolls_so_far: usize,
+    rng: &mut R,
+) -> RunDetail {
+    run_sim_inner(
+        config,
+        earned_prizes.to_vec(),
+        rolls_so_far,
+        false,
+        rng,
+        |_, _, _| {},
+    )
+}
+
+/// Same as [`run_sim`], but call `on_roll` after every roll with the roll's index in the run, the
+/// prize it gave and whether that roll earned the prize, which is its first roll unless the
+/// config needs more than one copy of it
+///
+/// This is for collecting stats the crate doesn't know about without storing the rolls and going
+/// over them again. The callback runs inside the simulation loop, so anything slow in it slows
+/// down every roll.
+///
+/// ```
+/// use rand::{rngs::SmallRng, SeedableRng};
+/// use uma_bingo::{run_sim_with_callback, SimConfig};
+///
+/// // The roll index of every new prize
+/// let mut new_prize_rolls = Vec::new();
+/// let rolls = run_sim_with_callback(
+///     &SimConfig::default(),
+///     &mut SmallRng::seed_from_u64(1),
+///     |roll_idx, _, was_new| {
+///         if was_new {
+///             new_prize_rolls.push(roll_idx);
+///         }
+///     },
+/// );
+///
+/// assert_eq!(new_prize_rolls.len(), uma_bingo::NUM_PRIZES);
+/// assert_eq!(new_prize_rolls.last(), Some(&(rolls.len() - 1)));
+/// ```
+pub fn run_sim_with_callback<R, F>(config: &SimConfig, rng: &mut R, on_roll: F) -> Vec<RollResult>
+where
+    R: Rng + ?Sized,
+    F: FnMut(usize, &RollResult, bool),
+{
+    run_sim_inner(config, vec![false; config.prizes], 0, false, rng, on_roll).rolls
+}
+
+/// Go over recorded rolls, e.g. a line of `--dump-rolls`, with the completion rules of `config`
+/// instead of rolling, calling `on_roll` like [`run_sim_with_callback`] does. Returns how many of
+/// the rolls it took to finish the run, or `None` if they run out first
+///
+/// Pity, bad luck protection, streaks and the deck already shaped the recorded rolls, so they
+/// don't come into it, and neither does `give_up`.
+pub fn replay_rolls<F>(config: &SimConfig, rolls: &[RollResult], mut on_roll: F) -> Option<usize>
+where
+    F: FnMut(usize, &RollResult, bool),
+{
+    let mut earned_prizes = vec![false; config.prizes];
+    let mut copies_rolled = vec![0; config.prizes];
+    for (roll_idx, roll_result) in rolls.iter().enumerate() {
+        if config.is_complete(&earned_prizes) {
+            return Some(roll_idx);
+        }
+        let prize_idx = usize::from(roll_result);
+        let was_new = earn_copy(config, &mut earned_prizes, &mut copies_rolled, prize_idx);
+        on_roll(roll_idx, roll_result, was_new);
+    }
+
+    config.is_complete(&earned_prizes).then_some(rolls.len())
+}
+
+/// [`run_sim_from`], optionally drawing each normal roll with [`sample_needed_first`] so that the
+/// run can be paired with an antithetic one, and calling `on_roll` after every roll
+fn run_sim_inner<R, F>(
+    config: &SimConfig,
+    mut earned_prizes: Vec<bool>,
+    rolls_so_far: usize,
+    needed_first: bool,
+    rng: &mut R,
+    mut on_roll: F,
+) -> RunDetail
+where
+    R: Rng + ?Sized,
+    F: FnMut(usize, &RollResult, bool),
+{
+    let mut results = Vec::new();
+    let mut rng_draws = 0;
+    let mut pity_rolls = 0;
+    let required = config.required();
+    let rarest = config.weights.as_ref().map_or(0, PrizeWeights::rarest);
+    let mut rolls_without_rarest = 0;
+    // The tokens still in the deck are the first `tokens_left`, drawing one swaps it behind them
+    let mut deck: Vec<usize> = (0..config.deck_size.unwrap_or(0))
+        .map(|token| token % config.prizes)
+        .collect();
+    let mut tokens_left = deck.len();
+    let mut copies_rolled = vec![0; config.prizes];
+
+    while !config.is_complete(&earned_prizes) {
+        if config
+            .give_up
+            .is_some_and(|give_up| rolls_so_far + results.len() >= give_up)
+        {
+            return RunDetail {
+                rolls: results,
+                rng_draws,
+                finished: false,
+                pity_rolls,
+            };
+        }
+
+        let prize_idx = if rolls_so_far + results.len() >= config.pity_threshold
+            && config.pity_mode != PityMode::None
+        {
+            if config.pity_mode == PityMode::Pool {
+                rng_draws += 1;
+            }
+            pity_rolls += 1;
+            pity_prize(
+                |prize_idx| required[prize_idx] && !earned_prizes[prize_idx],
+                config.prizes,
+                config.pity_mode,
+                rng,
+            )
+        } else if config
+            .blp_threshold
+            .is_some_and(|blp_threshold| rolls_without_rarest >= blp_threshold)
+        {
+            rarest
+        } else if config.streakiness > 0. && !results.is_empty() && {
+            // Deciding whether the roll repeats is a draw of its own
+            rng_draws += 1;
+            rng.random_bool(config.streakiness)
+        } {
+            usize::from(results.last().unwrap()) // okay to unwrap because results isn't empty
+        } else {
+            rng_draws += 1;
+            if !deck.is_empty() {
+                if tokens_left == 0 {
+                    tokens_left = deck.len();
+                }
+                let token_idx = rng.random_range(0..tokens_left);
+                tokens_left -= 1;
+                deck.swap(token_idx, tokens_left);
+                deck[tokens_left]
+            } else if needed_first {
+                sample_needed_first(
+                    config.prizes,
+                    config.weights.as_ref(),
+                    |prize_idx| required[prize_idx] && !earned_prizes[prize_idx],
+                    rng,
+                )
+            } else {
+                match &config.weights {
+                    Some(weights) => weights.sample(rng),
+                    None => rng.random_range(0..config.prizes),
+                }
+            }
+        };
+
+        if prize_idx == rarest {
+            rolls_without_rarest = 0;
+        } else {
+            rolls_without_rarest += 1;
+        }
+        let was_new = earn_copy(config, &mut earned_prizes, &mut copies_rolled, prize_idx);
+        let roll_result = RollResult::from(prize_idx);
+        on_roll(results.len(), &roll_result, was_new);
+        results.push(roll_result);
+    }
+
+    RunDetail {
+        rolls: results,
+        rng_draws,
+        finished: true,
+        pity_rolls,
+    }
+}
+
+/// Count a rolled copy of `prize_idx`, earning the prize once it has enough copies. Returns
+/// whether this roll earned it
+fn earn_copy(
+    config: &SimConfig,
+    earned_prizes: &mut [bool],
+    copies_rolled: &mut [usize],
+    prize_idx: usize,
+) -> bool {
+    copies_rolled[prize_idx] += 1;
+    let was_new = !earned_prizes[prize_idx] && copies_rolled[prize_idx] >= config.copies[prize_idx];
+    if was_new {
+        earned_prizes[prize_idx] = true;
+    }
+    was_new
+}
+
+/// Roll one of `prizes` prizes with the usual chances from a single uniform draw, where the low
+/// draws go to the prizes for which `needed` is true. Complementary draws then tend to give
+/// opposite outcomes, one a new prize and the other a duplicate
+fn sample_needed_first<R: Rng + ?Sized>(
+    prizes: usize,
+    weights: Option<&PrizeWeights>,
+    needed: impl Fn(usize) -> bool,
+    rng: &mut R,
+) -> usize {
+    let weight = |prize_idx: usize| weights.map_or(1., |weights| weights.weights()[prize_idx]);
+    let order = (0..prizes)
+        .filter(|&prize_idx| needed(prize_idx))
+        .chain((0..prizes).filter(|&prize_idx| !needed(prize_idx)))
+        .filter(|&prize_idx| weight(prize_idx) > 0.);
+    let total: f64 = (0..prizes).map(weight).sum();
+    let mut target = rng.random::<f64>() * total;
+    let mut prize = 0;
+    for prize_idx in order {
+        prize = prize_idx;
+        if target < weight(prize_idx) {
+            break;
+        }
+        target -= weight(prize_idx);
+    }
+    // Falling off the end can only be rounding, so it goes to the last prize that can be rolled
+    prize
+}
+
+/// Simulates runs in antithetic pairs, so that the average number of rolls settles with fewer
+/// runs than with independent ones
+///
+/// The second run of each pair replays the random numbers of the first one mirrored, so a lucky
+/// run tends to be followed by an unlucky one. Every run on its own still follows the rules of the
+/// config exactly, so the histogram and the mean are unbiased, but the two runs of a pair aren't
+/// independent: the uncertainty of the mean has to come from the pair averages instead of from
+/// the spread of the single runs.
+#[derive(Debug, Default)]
+pub struct AntitheticPairs {
+    /// The random numbers drawn by the first run of the pair in progress
+    pending: Option<Vec<u64>>,
+}
+
+impl AntitheticPairs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether the next run is the second one of a pair
+    pub fn completes_pair(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Same as [`run_sim_detailed`], alternating between the first and the second run of a pair
+    pub fn run_sim_detailed<R: Rng + ?Sized>(
+        &mut self,
+        config: &SimConfig,
+        rng: &mut R,
+    ) -> RunDetail {
+        match self.pending.take() {
+            None => {
+                let mut recording = RecordingRng {
+                    rng,
+                    draws: Vec::new(),
+                };
+                let detail = run_sim_inner(
+                    config,
+                    vec![false; config.prizes],
+                    0,
+                    true,
+                    &mut recording,
+                    |_, _, _| {},
+                );
+                self.pending = Some(recording.draws);
+                detail
+            }
+            Some(draws) => {
+                let mut mirrored = MirroredRng {
+                    rng,
+                    draws: draws.into_iter(),
+                };
+                run_sim_inner(
+                    config,
+                    vec![false; config.prizes],
+                    0,
+                    true,
+                    &mut mirrored,
+                    |_, _, _| {},
+                )
+            }
+        }
+    }
+}
+
+/// Passes through the numbers of `rng`, keeping a copy of each
+struct RecordingRng<'a, R: ?Sized> {
+    rng: &'a mut R,
+    draws: Vec<u64>,
+}
+
+impl<R: RngCore + ?Sized> RngCore for RecordingRng<'_, R> {
+    fn next_u32(&mut self) -> u32 {
+        self.next_u64() as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let draw = self.rng.next_u64();
+        self.draws.push(draw);
+        draw
+    }
+
+    fn fill_bytes(&mut self, dst: &mut [u8]) {
+        rand::rand_core::impls::fill_bytes_via_next(self, dst)
+    }
+}
+
+/// Replays `draws` with every bit flipped, which turns a uniform `u` into `1 - u`, and moves on to
+/// fresh numbers of `rng` once they run out
+struct MirroredRng<'a, R: ?Sized> {
+    rng: &'a mut R,
+    draws: std::vec::IntoIter<u64>,
+}
+
+impl<R: RngCore + ?Sized> RngCore for MirroredRng<'_, R> {
+    fn next_u32(&mut self) -> u32 {
+        self.next_u64() as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        match self.draws.next() {
+            Some(draw) => !draw,
+            None => self.rng.next_u64(),
+        }
+    }
+
+    fn fill_bytes(&mut self, dst: &mut [u8]) {
+        rand::rand_core::impls::fill_bytes_via_next(self, dst)
+    }
+}
+
+/// Same as [`run_sim`], but return which prizes had been earned after each roll instead of the
+/// rolls themselves
+///
+/// There is one entry per roll, and each one has an entry per prize that is true once the prize
+/// has been earned, so the last one of a run that collected every prize is all true.
+pub fn run_sim_trajectory<R: Rng + ?Sized>(config: &SimConfig, rng: &mut R) -> Vec<Vec<bool>> {
+    let mut earned_prizes = vec![false; config.prizes];
+    let mut copies_rolled = vec![0; config.prizes];
+    run_sim(config, rng)
+        .iter()
+        .map(|roll_result| {
+            let prize_idx = usize::from(roll_result);
+            earn_copy(config, &mut earned_prizes, &mut copies_rolled, prize_idx);
+            earned_prizes.clone()
+        })
+        .collect()
+}
+
+/// Estimate how many more rolls a run that has made `rolls_so_far` rolls and earned the prizes
+/// in `earned_prizes` needs on average, by carrying it on `runs` times with [`run_sim_from`]
+///
+/// Runs that give up count with the rolls they made before giving up.
+pub fn expected_remaining_rolls<R: Rng + ?Sized>(
+    config: &SimConfig,
+    earned_prizes: &[bool],
+    rolls_so_far: usize,
+    runs: usize,
+    rng: &mut R,
+) -> f64 {
+    let total_rolls: usize = (0..runs)
+        .map(|_| {
+            run_sim_from(config, earned_prizes, rolls_so_far, rng)
+                .rolls
+                .len()
+        })
+        .sum();
+    total_rolls as f64 / runs as f64
+}
+
+/// Pick the prize granted by a roll made after the pity threshold from the prizes out of
+/// `num_prizes` that are still `needed`
+fn pity_prize<R: Rng + ?Sized>(
+    needed: impl Fn(usize) -> bool,
+    num_prizes: usize,
+    pity_mode: PityMode,
+    rng: &mut R,
+) -> usize {
+    let mut candidates = (0..num_prizes).filter(|&prize_idx| needed(prize_idx));
+
+    match pity_mode {
+        // It's not truly random, but after the pity threshold we get a needed prize every time
+        // so it doesn't matter for the number of rolls
+        PityMode::Hard => candidates.next(),
+        PityMode::Pool => {
+            let needed_count = (0..num_prizes)
+                .filter(|&prize_idx| needed(prize_idx))
+                .count();
+            candidates.nth(rng.random_range(0..needed_count))
+        }
+        PityMode::None => unreachable!("rolls without pity never ask for a pity prize"),
+    }
+    .unwrap() // okay to unwrap because there must be at least one needed prize
+}
+
+/// The exact probability of collecting `required` specific prizes out of `prizes` in exactly
+/// `rolls` rolls with pity after `pity_threshold` rolls, indexed by `rolls`
+///
+/// Every prize is equally likely, so the only state that matters is how many of the required
+/// prizes have been earned. Rolls before the pity threshold earn a new one with probability
+/// `(required - k) / prizes`, and every roll after it earns one for sure.
+pub fn exact_pmf(prizes: usize, required: usize, pity_threshold: usize) -> Vec<f64> {
+    // earned[k] is the probability of having exactly k required prizes after the rolls so far
+    let mut earned = vec![0.; required + 1];
+    earned[0] = 1.;
+    let mut pmf = vec![earned[required]];
+    earned[required] = 0.;
+
+    while earned.iter().any(|&prob| prob > 0.) {
+        let mut next = vec![0.; required + 1];
+        for k in 0..required {
+            let new_prize_prob = if pmf.len() <= pity_threshold {
+                (required - k) as f64 / prizes as f64
+            } else {
+                1.
+            };
+            next[k] += earned[k] * (1. - new_prize_prob);
+            next[k + 1] += earned[k] * new_prize_prob;
+        }
+        pmf.push(next[required]);
+        next[required] = 0.;
+        earned = next;
+    }
+
+    pmf
+}
+
+/// The number of rolls it took to go from each number of different prizes to the next in a
+/// run, starting with the rolls from the first prize to the second
+///
+/// Without pity, going from `k` of `n` prizes to `k + 1` takes `n / (n - k)` rolls on average, so
+/// the gaps get longer as the run goes on.
+pub fn new_prize_gaps(rolls: &[RollResult]) -> Vec<usize> {
+    let roll_indices: Vec<usize> = new_prize_rolls(rolls)
+        .map(|(roll_idx, _)| roll_idx)
+        .collect();
+    roll_indices
+        .windows(2)
+        .map(|pair| pair[1] - pair[0])
+        .collect()
+}
+
+/// The index and the prize of every roll in `rolls` that rolled its prize for the first time
+pub(crate) fn new_prize_rolls(rolls: &[RollResult]) -> impl Iterator<Item = (usize, usize)> + '_ {
+    let mut rolled = Vec::new();
+    rolls
+        .iter()
+        .enumerate()
+        .filter_map(move |(roll_idx, roll_result)| {
+            let prize_idx = usize::from(roll_result);
+            if rolled.len() <= prize_idx {
+                rolled.resize(prize_idx + 1, false);
+            }
+            let was_new = !rolled[prize_idx];
+            rolled[prize_idx] = true;
+            was_new.then_some((roll_idx, prize_idx))
+        })
+}
+
+/// The exact average number of rolls to collect `required` specific prizes out of `prizes` when
+/// every prize is equally likely, with pity after `pity_threshold` rolls or without pity when it's
+/// `None`
+pub fn exact_mean_rolls(prizes: usize, required: usize, pity_threshold: Option<usize>) -> f64 {
+    match pity_threshold {
+        Some(pity_threshold) => exact_pmf(prizes, required, pity_threshold)
+            .iter()
+            .enumerate()
+            .map(|(rolls, prob)| rolls as f64 * prob)
+            .sum(),
+        None => expected_duplicates_without_pity(prizes, required) + required as f64,
+    }
+}
+
+/// The variance of the number of rolls to collect all of `num_prizes` equally likely prizes
+/// without pity
+///
+/// This is the coupon collector variance `n^2 * (1/1^2 + ... + 1/n^2) - n * H(n)`. Pity cuts the
+/// long runs short, so it doesn't hold at all once pity is on.
+pub fn theoretical_variance(num_prizes: usize) -> f64 {
+    let n = num_prizes as f64;
+    let (sum_inverse_squares, harmonic) = (1..=num_prizes)
+        .map(|k| k as f64)
+        .fold((0., 0.), |(squares, harmonic), k| {
+            (squares + 1. / (k * k), harmonic + 1. / k)
+        });
+    n * n * sum_inverse_squares - n * harmonic
+}
+
+/// The expected number of duplicate rolls, i.e. rolls that don't earn a new required prize,
+/// needed to collect `required` specific prizes out of `prizes` if there were no pity
+///
+/// This is the coupon collector expectation `n / required + ... + n / 2 + n / 1` for `n` prizes
+/// minus the `required` rolls that did earn something new. For all 8 of 8 prizes it is
+/// `8 * H(8) - 8`. Pity cuts the long runs short, so the simulated count should come in under
+/// this baseline.
+pub fn expected_duplicates_without_pity(prizes: usize, required: usize) -> f64 {
+    (1..=required)
+        .map(|left| prizes as f64 / left as f64)
+        .sum::<f64>()
+        - required as f64
+}
+
+/// Count the rolls needed to earn all `N` prizes, tracking earned prizes in a stack array
+///
+/// This follows the same rules as [`run_sim`] but only returns the number of rolls, so it never
+/// allocates. Use [`run_sim_with_prizes`] when the prize count isn't known at compile time.
+pub fn run_sim_const<const N: usize, R: Rng + ?Sized>(pity_mode: PityMode, rng: &mut R) -> usize {
+    count_rolls(&mut [false; N], pity_mode, PITY_THRESHOLD, rng)
+}
+
+/// Count the rolls needed to earn all `num_prizes` prizes with pity after `pity_threshold` rolls
+pub fn run_sim_with_prizes<R: Rng + ?Sized>(
+    num_prizes: usize,
+    pity_mode: PityMode,
+    pity_threshold: usize,
+    rng: &mut R,
+) -> usize {
+    count_rolls(&mut vec![false; num_prizes], pity_mode, pity_threshold, rng)
+}
+
+/// Roll until every prize in `earned_prizes` is earned and return the number of rolls it took
+fn count_rolls<R: Rng + ?Sized>(
+    earned_prizes: &mut [bool],
+    pity_mode: PityMode,
+    pity_threshold: usize,
+    rng: &mut R,
+) -> usize {
+    let mut rolls = 0;
+    let mut earned = 0;
+    while earned < earned_prizes.len() {
+        let prize_idx = if rolls < pity_threshold || pity_mode == PityMode::None {
+            rng.random_range(0..earned_prizes.len())
+        } else {
+            pity_prize(
+                |prize_idx| !earned_prizes[prize_idx],
+                earned_prizes.len(),
+                pity_mode,
+                rng,
+            )
+        };
+
+        if !earned_prizes[prize_idx] {
+            earned_prizes[prize_idx] = true;
+            earned += 1;
+        }
+        rolls += 1;
+    }
+
+    rolls
+}
+
+/// A discounted pack of rolls, like 10 rolls for the price of 9
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bundle {
+    pub rolls: usize,
+    pub price: f64,
+}
+
+impl FromStr for Bundle {
+    type Err = String;
+
+    /// Parse the number of rolls and the price of the bundle, e.g. `10:900`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (rolls, price) = s
+            .split_once(':')
+            .ok_or_else(|| "expected ROLLS:PRICE, e.g. 10:900".to_string())?;
+        let rolls = match rolls.trim().parse() {
+            Ok(rolls) if rolls > 0 => rolls,
+            _ => {
+                return Err(format!(
+                    "'{}' is not a number of rolls, expected a positive whole number",
+                    rolls.trim()
+                ))
+            }
+        };
+        match price.trim().parse::<f64>() {
+            Ok(price) if price.is_finite() && price >= 0. => Ok(Bundle { rolls, price }),
+            _ => Err(format!(
+                "'{}' is not a price, expected a non-negative number",
+                price.trim()
+            )),
+        }
+    }
+}
+
+/// What the rolls cost in the event's currency
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CostModel {
+    /// The price of a single roll
+    pub cost_per_roll: f64,
+    /// A pack of rolls that can be bought instead of single rolls
+    pub bundle: Option<Bundle>,
+}
+
+impl CostModel {
+    /// The cheapest way to buy at least `rolls` rolls, out of bundles and single rolls. A bundle
+    /// can be the cheapest way even if some of its rolls go unused
+    ///
+    /// Never decreases as `rolls` grows, so the percentiles of the cost are the cost of the
+    /// percentiles of the number of rolls.
+    pub fn cost(&self, rolls: usize) -> f64 {
+        let single = |rolls: usize| rolls as f64 * self.cost_per_roll;
+        match self.bundle {
+            Some(bundle) => (0..=rolls.div_ceil(bundle.rolls))
+                .map(|bundles| {
+                    bundles as f64 * bundle.price
+                        + single(rolls.saturating_sub(bundles * bundle.rolls))
+                })
+                .fold(f64::INFINITY, f64::min),
+            None => single(rolls),
+        }
+    }
+
+    /// The average cost of the runs in `summary`
+    pub fn mean_cost(&self, summary: &StatsSummary) -> f64 {
+        let total: f64 = summary
+            .histogram
+            .iter()
+            .map(|(&rolls, &count)| self.cost(rolls) * count as f64)
+            .sum();
+        total / summary.runs as f64
+    }
+}
+
+/// Roll exactly `pulls` times with the prizes, chances and copies of `config`, starting from the
+/// prizes already in `earned`, and return how many new prizes were earned
+///
+/// The session doesn't know how many rolls came before it, so pity never applies. Every prize
+/// counts, whether the completion rules need it or not, and the rolls stop early once there is
+/// nothing new left to earn.
+pub fn simulate_session<R: Rng + ?Sized>(
+    config: &SimConfig,
+    earned: &[bool],
+    pulls: usize,
+    rng: &mut R,
+) -> usize {
+    let session = SimConfig {
+        pity_mode: PityMode::None,
+        sets: PrizeSets::first(config.prizes),
+        until: None,
+        give_up: Some(pulls),
+        ..config.clone()
+    };
+    let mut new_prizes = 0;
+    run_sim_inner(&session, earned.to_vec(), 0, false, rng, |_, _, was_new| {
+        new_prizes += usize::from(was_new)
+    });
+
+    new_prizes
+}
+
+/// The seed of the seeded tests, so that each of them rolls the same runs every time
+#[cfg(test)]
+pub(crate) const TEST_SEED: u64 = 1;
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::SmallRng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn weighted_sampling_follows_the_weights() {
+        let config = SimConfig {
+            pity_mode: PityMode::None,
+            weights: Some(PrizeWeights::new(vec![100., 1., 1., 1., 1., 1., 1., 1.]).unwrap()),
+            ..SimConfig::default()
+        };
+        let mut rng = SmallRng::seed_from_u64(TEST_SEED);
+        let runs = 10_000;
+        let first_is_heavy = (0..runs)
+            .filter(|_| run_sim(&config, &mut rng)[0] == RollResult::from(0))
+            .count();
+
+        // The expected share is 100 / 107, and its standard error over 10000 runs is about 0.0025,
+        // so 0.01 is 4 standard errors
+        let share = first_is_heavy as f64 / runs as f64;
+        assert!(
+            (share - 100. / 107.).abs() < 0.01,
+            "prize 0 came first in {} of the runs",
+            share
+        );
+    }
+
+    #[test]
+    fn run_sim_const_matches_the_runtime_prize_count() {
+        for seed in 0..50 {
+            assert_eq!(
+                run_sim_const::<1, _>(PityMode::Hard, &mut SmallRng::seed_from_u64(seed)),
+                1
+            );
+            assert_eq!(
+                run_sim_const::<4, _>(PityMode::Hard, &mut SmallRng::seed_from_u64(seed)),
+                run_sim_with_prizes(
+                    4,
+                    PityMode::Hard,
+                    PITY_THRESHOLD,
+                    &mut SmallRng::seed_from_u64(seed)
+                )
+            );
+        }
+
+        // Past the threshold every roll is a new prize, and one was earned before it
+        let mut rng = SmallRng::seed_from_u64(TEST_SEED);
+        let runs = 20_000;
+        let mut total_rolls = 0;
+        for _ in 0..runs {
+            let rolls = run_sim_const::<NUM_PRIZES, _>(PityMode::Hard, &mut rng);
+            assert!((NUM_PRIZES..PITY_THRESHOLD + NUM_PRIZES).contains(&rolls));
+            total_rolls += rolls;
+        }
+        let mean = total_rolls as f64 / runs as f64;
+        let exact_mean = exact_mean_rolls(NUM_PRIZES, NUM_PRIZES, Some(PITY_THRESHOLD));
+        assert!((mean - exact_mean).abs() < 0.2, "mean {}", mean);
+    }
+
+    #[test]
+    fn pool_pity_never_rolls_a_duplicate_past_the_threshold() {
+        let config = SimConfig {
+            pity_mode: PityMode::Pool,
+            pity_threshold: 5,
+            ..SimConfig::default()
+        };
+        let mut rng = SmallRng::seed_from_u64(TEST_SEED);
+        for _ in 0..2000 {
+            let rolls = run_sim(&config, &mut rng);
+            assert!(rolls.len() < config.pity_threshold + NUM_PRIZES);
+            for (roll_idx, roll_result) in rolls.iter().enumerate().skip(config.pity_threshold) {
+                assert!(
+                    !rolls[..roll_idx].contains(roll_result),
+                    "roll {} of {:?} is a duplicate",
+                    roll_idx,
+                    rolls
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn every_roll_without_pity_is_one_rng_draw() {
+        let no_pity = SimConfig {
+            pity_mode: PityMode::None,
+            ..SimConfig::default()
+        };
+        let hard_pity = SimConfig::default();
+        let mut rng = SmallRng::seed_from_u64(TEST_SEED);
+        for _ in 0..1000 {
+            let run = run_sim_detailed(&no_pity, &mut rng);
+            assert_eq!(run.rng_draws, run.rolls.len());
+
+            // Hard pity grants its prizes without drawing
+            let run = run_sim_detailed(&hard_pity, &mut rng);
+            assert_eq!(run.rng_draws, run.rolls.len().min(hard_pity.pity_threshold));
+        }
+
+        // Every roll after the first draws whether it repeats, and the ones that don't repeat draw
+        // their prize as well
+        let streaky = SimConfig {
+            streakiness: 0.5,
+            ..no_pity
+        };
+        for _ in 0..1000 {
+            let run = run_sim_detailed(&streaky, &mut rng);
+            let repeats = run
+                .rolls
+                .windows(2)
+                .filter(|pair| pair[0] == pair[1])
+                .count();
+            assert!(run.rng_draws + repeats >= 2 * run.rolls.len() - 1);
+            assert!(run.rng_draws < 2 * run.rolls.len());
+        }
+    }
+
+    #[test]
+    fn one_set_per_prize_is_the_default_bingo() {
+        let sets: PrizeSets = "0;1;2;3;4;5;6;7".parse().unwrap();
+        assert_eq!(sets, PrizeSets::individual());
+
+        let with_sets = SimConfig {
+            sets,
+            ..SimConfig::default()
+        };
+        for pity_mode in [PityMode::None, PityMode::Hard, PityMode::Pool] {
+            let default = SimConfig {
+                pity_mode,
+                ..SimConfig::default()
+            };
+            let with_sets = SimConfig {
+                pity_mode,
+                ..with_sets.clone()
+            };
+            for seed in 0..200 {
+                let rolls = run_sim(&with_sets, &mut SmallRng::seed_from_u64(seed));
+                assert_eq!(rolls, run_sim(&default, &mut SmallRng::seed_from_u64(seed)));
+                // The plain roll counter that doesn't know about sets
+                assert_eq!(
+                    rolls.len(),
+                    run_sim_with_prizes(
+                        NUM_PRIZES,
+                        pity_mode,
+                        PITY_THRESHOLD,
+                        &mut SmallRng::seed_from_u64(seed)
+                    )
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn bad_luck_protection_grants_the_rarest_prize_in_time() {
+        let blp_threshold = 10;
+        let config = SimConfig {
+            pity_mode: PityMode::None,
+            weights: Some(PrizeWeights::new(vec![1., 1., 1., 1., 1., 1., 1., 0.01]).unwrap()),
+            blp_threshold: Some(blp_threshold),
+            ..SimConfig::default()
+        };
+        let rarest = config.weights.as_ref().unwrap().rarest();
+        assert_eq!(rarest, 7);
+
+        let mut rng = SmallRng::seed_from_u64(TEST_SEED);
+        for _ in 0..2000 {
+            let rolls = run_sim(&config, &mut rng);
+            // The rarest prize never goes more than `blp_threshold` rolls without showing up
+            let mut rolls_without_rarest = 0;
+            for roll_result in &rolls {
+                if usize::from(roll_result) == rarest {
+                    rolls_without_rarest = 0;
+                } else {
+                    rolls_without_rarest += 1;
+                    assert!(
+                        rolls_without_rarest <= blp_threshold,
+                        "no prize {} in {:?}",
+                        rarest,
+                        rolls
+                    );
+                }
+            }
+            assert!(rolls
+                .iter()
+                .any(|roll_result| usize::from(roll_result) == rarest));
+        }
+    }
+
+    #[test]
+    fn zero_streakiness_rolls_independently() {
+        let config = SimConfig {
+            pity_mode: PityMode::None,
+            streakiness: 0.,
+            ..SimConfig::default()
+        };
+        for seed in 0..200 {
+            // The same rolls as drawing uniformly until every prize shows up
+            let mut rng = SmallRng::seed_from_u64(seed);
+            let mut expected = Vec::new();
+            let mut earned = [false; NUM_PRIZES];
+            while earned.contains(&false) {
+                let prize_idx = rng.random_range(0..NUM_PRIZES);
+                earned[prize_idx] = true;
+                expected.push(RollResult::from(prize_idx));
+            }
+
+            let rolls = run_sim(&config, &mut SmallRng::seed_from_u64(seed));
+            assert_eq!(rolls, expected);
+        }
+    }
+
+    #[test]
+    fn zero_weight_required_prize_without_pity_is_rejected() {
+        let weights = PrizeWeights::new(vec![1., 1., 1., 1., 1., 1., 1., 0.]).unwrap();
+        let no_pity = SimConfig {
+            pity_mode: PityMode::None,
+            weights: Some(weights),
+            ..SimConfig::default()
+        };
+        let err = no_pity.validate().unwrap_err();
+        assert!(err.contains("prize index 7"), "{}", err);
+
+        // Anything that still gets the prize or doesn't need it makes the runs finish
+        assert!(SimConfig {
+            pity_mode: PityMode::Hard,
+            ..no_pity.clone()
+        }
+        .validate()
+        .is_ok());
+        assert!(SimConfig {
+            give_up: Some(100),
+            ..no_pity.clone()
+        }
+        .validate()
+        .is_ok());
+        assert!(SimConfig {
+            blp_threshold: Some(10),
+            ..no_pity.clone()
+        }
+        .validate()
+        .is_ok());
+        assert!(SimConfig {
+            sets: "0,1,2,3,4,5,6".parse().unwrap(),
+            ..no_pity
+        }
+        .validate()
+        .is_ok());
+
+        assert!(PrizeWeights::new(vec![0.; NUM_PRIZES]).is_err());
+    }
+
+    #[test]
+    fn deck_of_one_token_per_prize_takes_one_roll_each() {
+        let mut rng = SmallRng::seed_from_u64(TEST_SEED);
+        for pity_mode in [PityMode::None, PityMode::Hard] {
+            let config = SimConfig {
+                pity_mode,
+                deck_size: Some(NUM_PRIZES),
+                ..SimConfig::default()
+            };
+            for _ in 0..500 {
+                let rolls = run_sim(&config, &mut rng);
+                assert_eq!(rolls.len(), NUM_PRIZES);
+                let mut prizes: Vec<usize> = rolls.iter().map(usize::from).collect();
+                prizes.sort_unstable();
+                assert_eq!(prizes, (0..NUM_PRIZES).collect::<Vec<_>>());
+            }
+        }
+
+        // With two tokens of each prize, every prize has been drawn by the time one token is left
+        let config = SimConfig {
+            pity_mode: PityMode::None,
+            deck_size: Some(2 * NUM_PRIZES),
+            ..SimConfig::default()
+        };
+        for _ in 0..500 {
+            assert!(run_sim(&config, &mut rng).len() < 2 * NUM_PRIZES);
+        }
+    }
+
+    #[test]
+    fn expected_remaining_rolls_at_the_boundaries() {
+        let config = SimConfig::default();
+        let mut rng = SmallRng::seed_from_u64(TEST_SEED);
+
+        assert_eq!(
+            expected_remaining_rolls(&config, &[true; NUM_PRIZES], 30, 100, &mut rng),
+            0.
+        );
+
+        // From scratch it's the whole expected run
+        let from_scratch =
+            expected_remaining_rolls(&config, &[false; NUM_PRIZES], 0, 20_000, &mut rng);
+        let exact_mean = exact_mean_rolls(NUM_PRIZES, NUM_PRIZES, Some(config.pity_threshold));
+        assert!(
+            (from_scratch - exact_mean).abs() < 0.2,
+            "{} rolls left from scratch, expected {}",
+            from_scratch,
+            exact_mean
+        );
+
+        // Past the threshold, each missing prize takes exactly one more roll
+        let mut earned_prizes = [true; NUM_PRIZES];
+        earned_prizes[3] = false;
+        earned_prizes[5] = false;
+        assert_eq!(
+            expected_remaining_rolls(
+                &config,
+                &earned_prizes,
+                config.pity_threshold,
+                100,
+                &mut rng
+            ),
+            2.
+        );
+    }
+
+    #[test]
+    fn trajectory_ends_with_every_prize_earned() {
+        let config = SimConfig::default();
+        for seed in 0..200 {
+            let trajectory = run_sim_trajectory(&config, &mut SmallRng::seed_from_u64(seed));
+            let rolls = run_sim(&config, &mut SmallRng::seed_from_u64(seed));
+            assert_eq!(trajectory.len(), rolls.len());
+            assert_eq!(trajectory.last(), Some(&vec![true; NUM_PRIZES]));
+            // Prizes are never lost, so every roll has the prizes earned by the ones before it
+            for pair in trajectory.windows(2) {
+                assert!(pair[0]
+                    .iter()
+                    .zip(&pair[1])
+                    .all(|(&before, &after)| after || !before));
+            }
+        }
+    }
+
+    #[test]
+    fn conditions_parse_with_and_binding_tighter_than_or() {
+        use Condition::{And, Or, Prize};
+        let prize = |prize_idx| Box::new(Prize(prize_idx));
+
+        assert_eq!(
+            "prize1 or prize2 and prize3".parse::<Condition>().unwrap(),
+            Or(prize(0), Box::new(And(prize(1), prize(2))))
+        );
+        let grouped: Condition = "(prize1 or prize2) and prize3".parse().unwrap();
+        assert_eq!(grouped, And(Box::new(Or(prize(0), prize(1))), prize(2)));
+        assert_eq!(grouped.to_string(), "(prize1 or prize2) and prize3");
+        assert_eq!(
+            grouped.mentioned(NUM_PRIZES),
+            [true, true, true, false, false, false, false, false]
+        );
+        assert!(grouped.is_met(&[false, true, true, false, false, false, false, false]));
+        assert!(!grouped.is_met(&[true, true, false, false, false, false, false, false]));
+
+        for bad in [
+            "",
+            "prize0",
+            "prize1 and",
+            "(prize1",
+            "prize1 prize2",
+            "prize1 xor prize2",
+        ] {
+            assert!(bad.parse::<Condition>().is_err(), "parsed '{}'", bad);
+        }
+
+        // The parser doesn't know how many prizes there are, so a prize past them is up to the
+        // config to reject
+        let past_the_prizes = SimConfig {
+            until: Some("prize1 and prize9".parse().unwrap()),
+            ..SimConfig::default()
+        };
+        assert!(past_the_prizes.validate().is_err());
+        assert!(SimConfig {
+            prizes: 9,
+            copies: vec![1; 9],
+            ..past_the_prizes
+        }
+        .validate()
+        .is_ok());
+    }
+
+    #[test]
+    fn condition_on_every_prize_matches_the_default_bingo() {
+        let every_prize: Condition =
+            "prize1 and prize2 and prize3 and prize4 and prize5 and prize6 and prize7 and prize8"
+                .parse()
+                .unwrap();
+        let until = SimConfig {
+            until: Some(every_prize),
+            ..SimConfig::default()
+        };
+        for seed in 0..200 {
+            assert_eq!(
+                run_sim(&until, &mut SmallRng::seed_from_u64(seed)),
+                run_sim(&SimConfig::default(), &mut SmallRng::seed_from_u64(seed))
+            );
+        }
+    }
+
+    #[test]
+    fn simulated_spread_matches_the_theoretical_variance() {
+        assert_eq!(theoretical_variance(1), 0.);
+        // Waiting for the second of two prizes is geometric with p = 1/2, so a variance of 2
+        assert!((theoretical_variance(2) - 2.).abs() < 1e-12);
+
+        let mut rng = SmallRng::seed_from_u64(TEST_SEED);
+        for num_prizes in [4, NUM_PRIZES] {
+            let runs = 40_000;
+            let lengths: Vec<f64> = (0..runs)
+                .map(|_| {
+                    run_sim_with_prizes(num_prizes, PityMode::None, PITY_THRESHOLD, &mut rng) as f64
+                })
+                .collect();
+            let mean = lengths.iter().sum::<f64>() / runs as f64;
+            let variance = lengths
+                .iter()
+                .map(|rolls| (rolls - mean).powi(2))
+                .sum::<f64>()
+                / (runs - 1) as f64;
+            let expected = theoretical_variance(num_prizes).sqrt();
+            assert!(
+                (variance.sqrt() - expected).abs() < 0.02 * expected,
+                "standard deviation of {} with {} prizes, expected {}",
+                variance.sqrt(),
+                num_prizes,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn replaying_a_known_sequence_finds_where_it_finished() {
+        let recorded = |prizes: &[usize]| -> Vec<RollResult> {
+            prizes
+                .iter()
+                .map(|&prize_idx| RollResult::from(prize_idx))
+                .collect()
+        };
+        let config = SimConfig::default();
+
+        // Prize 7 comes last on roll 10, and the rolls after it are ignored
+        let rolls = recorded(&[0, 1, 1, 2, 3, 4, 0, 5, 6, 7, 2, 3]);
+        let mut new_prizes = 0;
+        let finished = replay_rolls(&config, &rolls, |_, _, was_new| {
+            new_prizes += usize::from(was_new)
+        });
+        assert_eq!(finished, Some(10));
+        assert_eq!(new_prizes, NUM_PRIZES);
+
+        assert_eq!(replay_rolls(&config, &rolls[..9], |_, _, _| {}), None);
+
+        // With only the first four prizes needed, it's done on roll 5
+        let first_four = SimConfig {
+            sets: PrizeSets::first(4),
+            ..SimConfig::default()
+        };
+        assert_eq!(replay_rolls(&first_four, &rolls, |_, _, _| {}), Some(5));
+
+        // A seeded run replays to its own length
+        let run = run_sim(&config, &mut SmallRng::seed_from_u64(TEST_SEED));
+        assert_eq!(replay_rolls(&config, &run, |_, _, _| {}), Some(run.len()));
+    }
+}
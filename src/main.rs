@@ -1,155 +1,4464 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, IsTerminal, Write},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use chrono::Local;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use comfy_table::{
+    presets::{ASCII_FULL_CONDENSED, NOTHING},
+    CellAlignment, Table,
+};
+use plotters::{coord::Shift, prelude::*};
+use rand::{
+    rngs::{SmallRng, StdRng},
+    Rng, SeedableRng,
+};
+#[cfg(feature = "sqlite")]
+use uma_bingo::simulator::RunRecord;
+use uma_bingo::{
+    analyze_histogram,
+    chart::{
+        draw_cdf_chart, draw_hazard_chart, draw_heatmap, draw_histogram_into,
+        draw_overlaid_histograms, draw_seed_comparison, draw_summary_chart, HistogramOptions,
+        LabelRotation,
+    },
+    event::EventConfig,
+    exact_mean_rolls, exact_pmf, expected_duplicates_without_pity, expected_remaining_rolls,
+    gini_coefficient, ks_test,
+    lengths::{read_lengths, write_lengths, ReadLengthsError},
+    replay_rolls, run_sim,
+    simulator::{
+        write_roll_sequence, Aggregate, CompareError, EarlyEvent, PitySolution, RunLimit,
+        SimulateOptions, Simulator,
+    },
+    theoretical_variance, Bundle, Condition, CostModel, PityMode, PrizeSets, PrizeWeights,
+    RollResult, SimConfig, StatsSummary, NUM_PRIZES, PITY_THRESHOLD,
+};
+
+/// Simulate the uma-bingo event and chart how many rolls it takes to earn every prize
+///
+/// Without a subcommand, the runs are simulated and everything asked for is printed and written
+/// in one go. The subcommands split that up, so that the runs can be simulated once and charted
+/// or analyzed as often as needed.
+#[derive(Parser)]
+#[command(
+    args_conflicts_with_subcommands = true,
+    after_help = "Exit status: 0 on success, 1 when --self-test fails, 2 for invalid options, 3 when an output file can't be written and 130 when interrupted with Ctrl-C"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    args: Args,
+}
+
+/// The subcommands, each of which takes the same options as the command on its own
+#[derive(Subcommand)]
+enum Command {
+    /// Simulate the runs and only write the number of rolls of each one to a file, e.g.
+    /// `uma-bingo simulate --runs 1e6 --out results.csv`
+    Simulate {
+        /// Where to write the runs: as a CSV with a `rolls` column if PATH ends in `.csv`, and in
+        /// the binary format of --dump-lengths otherwise
+        #[arg(long, value_name = "PATH")]
+        out: PathBuf,
+
+        #[command(flatten)]
+        args: Args,
+    },
+    /// Chart the runs of a file written by `simulate`, e.g. `uma-bingo plot results.csv`
+    Plot {
+        /// The file written by `simulate`
+        path: PathBuf,
+
+        #[command(flatten)]
+        args: Args,
+    },
+    /// Print the summary statistics of the runs of a file written by `simulate`
+    Stats {
+        /// The file written by `simulate`
+        path: PathBuf,
+
+        #[command(flatten)]
+        args: Args,
+    },
+}
+
+/// What a subcommand does with its file of runs
+#[derive(Clone, Debug)]
+enum RunsFile {
+    Write(PathBuf),
+    Plot(PathBuf),
+    Stats(PathBuf),
+}
+
+#[derive(clap::Args, Clone)]
+struct Args {
+    /// What the subcommand does, if there is one
+    #[arg(skip)]
+    runs_file: Option<RunsFile>,
+
+    /// Read options from this TOML file, e.g. `runs = 1000` and `pity-mode = "pool"`. Options
+    /// given on the command line take precedence
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Re-run the simulation every time the --config file is saved
+    #[cfg(feature = "watch")]
+    #[arg(long, requires = "config")]
+    watch: bool,
+
+    /// Number of simulations to run
+    #[arg(long, default_value_t = 1000000, value_parser = parse_positive)]
+    runs: usize,
+
+    /// Keep simulating for this many seconds instead of a fixed number of runs
+    #[arg(long, value_name = "SECS", conflicts_with = "runs")]
+    duration: Option<f64>,
+
+    /// Also render a heatmap of the roll on which each prize is first earned to this path
+    #[arg(long, value_name = "PATH")]
+    heatmap: Option<PathBuf>,
+
+    /// Also render the hazard to this path: the chance that a run finishes on each roll given
+    /// that it got that far. Pity shows up as a spike at the threshold
+    #[arg(long, value_name = "PATH")]
+    hazard: Option<PathBuf>,
+
+    /// Also render the cumulative distribution to this path: the chance of finishing within each
+    /// number of rolls, with the number of rolls that BUDGET percent of runs finish within marked
+    #[arg(long, value_name = "PATH")]
+    cdf: Option<PathBuf>,
+
+    /// The percentage of runs the --cdf chart marks the budget for
+    #[arg(long, value_name = "PERCENT", default_value_t = 95., value_parser = parse_budget, requires = "cdf")]
+    budget: f64,
+
+    /// Also report how many days it takes to earn every prize for a player who gets R rolls a
+    /// day. Pity carries over from one day to the next
+    #[arg(long, value_name = "R")]
+    rolls_per_day: Option<NonZeroUsize>,
+
+    /// Print the chance of having earned each prize within the first K rolls. Runs stop once
+    /// they finish, so prizes that --sets doesn't need are only counted up to that point
+    #[arg(long, value_name = "K")]
+    per_prize_by: Option<usize>,
+
+    /// Print the average number of rolls it took to go from each number of different prizes to
+    /// the next
+    #[arg(long)]
+    gaps: bool,
+
+    /// Print the chance of earning every prize within N rolls
+    #[arg(long, value_name = "N")]
+    query_within: Option<usize>,
+
+    /// Count runs that took exactly N rolls as within N for --query-within, i.e. P(rolls ≤ N).
+    /// This is the default
+    #[arg(long, overrides_with = "within_exclusive")]
+    within_inclusive: bool,
+
+    /// Only count runs that took fewer than N rolls as within N for --query-within, i.e.
+    /// P(rolls < N)
+    #[arg(long, overrides_with = "within_inclusive", requires = "query_within")]
+    within_exclusive: bool,
+
+    /// Print the chance that this prize, numbered from 1, is the last new prize of a run
+    #[arg(long, value_name = "PRIZE", value_parser = parse_prize_number)]
+    prob_last: Option<usize>,
+
+    /// Also simulate every prize count from 1 to all of them and render their mean number of rolls with 95%
+    /// confidence intervals to this path. Each count keeps the weights and copies of its prizes and
+    /// has to collect all of them
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["sets", "until"])]
+    summary_chart: Option<PathBuf>,
+
+    /// What happens to rolls after the pity threshold: `hard` grants the first unearned prize,
+    /// `pool` grants a random unearned prize and `none` turns pity off
+    #[arg(long, default_value_t = PityMode::Hard)]
+    pity_mode: PityMode,
+
+    /// Number of rolls after which pity kicks in. Takes precedence over the pity threshold of an
+    /// --event file [default: 25]
+    #[arg(long, value_name = "N")]
+    pity_threshold: Option<usize>,
+
+    /// Instead of the usual output, search for the pity threshold whose average number of rolls
+    /// is closest to M, simulating --runs runs for every threshold tried. Every threshold needs the
+    /// same seed, so --rng thread, which can't be seeded, uses the small generator instead
+    #[arg(
+        long,
+        value_name = "M",
+        conflicts_with_all = ["duration", "repeat", "compare_seeds", "session_size", "remaining"]
+    )]
+    solve_pity_for_mean: Option<f64>,
+
+    /// Finish once every one of these sets of prizes has been collected instead of once every
+    /// prize has. Sets are zero-based prize indices separated by commas, and are separated from
+    /// each other by semicolons, e.g. "0,1,2;3,4;5,6,7"
+    #[arg(long)]
+    sets: Option<PrizeSets>,
+
+    /// Finish once this condition on the earned prizes holds instead of once every prize has been
+    /// collected, e.g. "prize1 and prize3 and (prize5 or prize6)". Prizes are numbered from 1 and
+    /// `and` binds tighter than `or`
+    #[arg(long, value_name = "EXPR", conflicts_with = "sets")]
+    until: Option<Condition>,
+
+    /// Repeat the whole experiment this many times and report how much its average varies
+    #[arg(long, value_name = "R", default_value_t = 1, value_parser = parse_positive)]
+    repeat: usize,
+
+    /// Instead of the usual output, chart the histograms from this many different seeds on top of
+    /// each other to show how much they vary. The seeds count up from --seed, or from 0 without
+    /// one. The chart goes to the --output-template with "-{N}-seeds" added to the file name
+    #[arg(long, value_name = "N", conflicts_with_all = ["duration", "repeat"])]
+    compare_seeds: Option<u64>,
+
+    /// Instead of the usual output, run the simulation once with each of these pity modes from
+    /// the same seed, then compare them in a table and chart their histograms on top of each other.
+    /// The chart goes to the --output-template with "-pity-{modes}" added to the file name
+    #[arg(
+        long,
+        value_name = "MODES",
+        value_delimiter = ',',
+        conflicts_with_all = [
+            "duration",
+            "repeat",
+            "compare_seeds",
+            "session_size",
+            "remaining",
+            "solve_pity_for_mean",
+            "pity_mode",
+        ]
+    )]
+    compare_pity: Option<Vec<PityMode>>,
+
+    /// Only label every n-th roll count on the x-axis of the histogram. Picked from the width of
+    /// the axis when not given
+    #[arg(long, value_name = "N")]
+    label_stride: Option<NonZeroUsize>,
+
+    /// Rotate the x-axis labels of the histogram by this many degrees
+    #[arg(long, value_name = "0|90|270", default_value_t = LabelRotation::None)]
+    label_rotation: LabelRotation,
+
+    /// Print the statistics and histogram as bordered tables. The default plain output is easier
+    /// for scripts to parse
+    #[arg(long)]
+    pretty: bool,
+
+    /// Also print the histogram as a bar chart that fits the width of the terminal
+    #[arg(long)]
+    term_chart: bool,
+
+    /// Never color the printed summary. Color is also turned off when NO_COLOR is set or the
+    /// output isn't a terminal, and needs the `color` feature
+    #[arg(long)]
+    no_color: bool,
+
+    /// Number of decimal places to print floating point statistics with. The JSON output always
+    /// keeps full precision
+    #[arg(long, value_name = "D", default_value_t = 3)]
+    precision: usize,
+
+    /// Percentiles of the number of rolls to report
+    #[arg(long, value_delimiter = ',', default_values_t = [50., 90., 99.])]
+    percentiles: Vec<f64>,
+
+    /// Also write a self-contained HTML report with the histogram and summary statistics to this
+    /// path
+    #[arg(long, value_name = "PATH")]
+    html: Option<PathBuf>,
+
+    /// Print how long the simulation, the statistics and writing the outputs took. The statistics
+    /// include printing them, and --summary-chart isn't counted
+    #[arg(long)]
+    profile: bool,
+
+    /// Print additional statistics about the runs
+    #[arg(long)]
+    extended_stats: bool,
+
+    /// Print the stats of the runs that got a prize from pity separately from the ones that
+    /// finished before pity kicked in
+    #[arg(long)]
+    split_by_pity: bool,
+
+    /// Print the stats of the runs where this happened early on separately from the others:
+    /// `duplicate-within:K` for a duplicate in the first K rolls, or `prizes-within:K:N` for at
+    /// least N different prizes in the first K rolls
+    #[arg(long, value_name = "EVENT", value_parser = parse_early_event)]
+    condition_on: Option<EarlyEvent>,
+
+    /// Check the random number generator by printing the lag-1 autocorrelation of the numbers of
+    /// rolls of consecutive runs, which should be close to 0
+    #[arg(long)]
+    rng_diagnostics: bool,
+
+    /// Simulate the runs in antithetic pairs, where the second run of each pair mirrors the
+    /// random numbers of the first, and report the standard error of the average from the pair
+    /// averages. Each run still follows the rules, but lucky and unlucky runs partly cancel out:
+    /// on the default rules the standard error drops by about 12%, so the same precision takes
+    /// about a fifth fewer runs. The pairing also shows up in --rng-diagnostics
+    #[arg(long, conflicts_with = "give_up")]
+    variance_reduction: bool,
+
+    /// Instead of the usual output, check that the simulator behaves as it should on this build
+    /// and print whether each check passed
+    #[arg(long)]
+    self_test: bool,
+
+    /// Before simulating, print the prizes from rarest to most common with their chance per roll
+    /// and the average number of rolls to get each one
+    #[arg(long)]
+    rarity_report: bool,
+
+    /// Also explain in plain words what was simulated and how the result compares to the theory
+    #[arg(long)]
+    explain: bool,
+
+    /// Also write the summary statistics to this path as JSON
+    #[arg(long, value_name = "PATH")]
+    json: Option<PathBuf>,
+
+    /// Also write the summary statistics to this path as a CSV with one `stat,value` row each
+    #[arg(long, value_name = "PATH")]
+    csv: Option<PathBuf>,
+
+    /// Group every roll count at or above N into a single ">=N" bucket on the histogram. The
+    /// printed stats still use the true roll counts. N has to be more than the fewest rolls a run
+    /// can take, so that at least one bucket comes before the overflow bucket
+    #[arg(long, value_name = "N")]
+    tail_cap: Option<usize>,
+
+    /// Instead of the usual output, simulate --runs sessions of this many pulls from no prizes
+    /// and report how many new prizes each session earned
+    #[arg(long, value_name = "K", conflicts_with_all = ["duration", "repeat", "compare_seeds"])]
+    session_size: Option<usize>,
+
+    /// Instead of the usual output, estimate the average number of rolls left from a run in
+    /// progress with --runs runs. The progress is written as MASK:ROLLS, where MASK has a
+    /// character for each prize, the k-th of which is 1 if prize k has been earned, and ROLLS is
+    /// the number of rolls made so far, e.g. "01101000:12"
+    #[arg(
+        long,
+        value_name = "MASK:ROLLS",
+        value_parser = parse_progress,
+        conflicts_with_all = ["duration", "repeat", "compare_seeds", "session_size"]
+    )]
+    remaining: Option<Progress>,
+
+    /// Instead of the usual output, go over the runs recorded by --dump-rolls at this path with
+    /// the completion rules, without rolling anything, and print how every roll changes the
+    /// earned prizes along with when each run finished
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = [
+            "duration",
+            "repeat",
+            "compare_seeds",
+            "compare_pity",
+            "session_size",
+            "remaining",
+            "solve_pity_for_mean",
+            "sweep",
+        ]
+    )]
+    replay: Option<PathBuf>,
+
+    /// Write how often each prize was the k-th one collected to this path as a CSV
+    #[arg(long, value_name = "PATH")]
+    order_matrix: Option<PathBuf>,
+
+    /// Write every roll of every finished run to this path, one run per line as space-separated
+    /// prize indices. This keeps all the rolls in memory until the end, unless that could take
+    /// more than --max-memory
+    #[arg(long, value_name = "PATH")]
+    dump_rolls: Option<PathBuf>,
+
+    /// Write the number of rolls of every finished run to this path in a compact binary format
+    /// that --load-lengths can read back
+    #[arg(long, value_name = "PATH")]
+    dump_lengths: Option<PathBuf>,
+
+    /// Instead of simulating, print the stats of the runs in this --dump-lengths file and draw
+    /// their histogram to the --output-template, so a big simulation can be re-plotted without
+    /// running it again
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = [
+            "duration",
+            "repeat",
+            "compare_seeds",
+            "compare_pity",
+            "session_size",
+            "remaining",
+            "solve_pity_for_mean",
+            "sweep",
+            "replay",
+        ]
+    )]
+    load_lengths: Option<PathBuf>,
+
+    /// Append the histogram to this CSV, creating it if needed, with every row tagged with when
+    /// and by which process it was written. Lets the results of many runs be collected in one
+    /// file
+    #[arg(long, value_name = "PATH")]
+    append_csv: Option<PathBuf>,
+
+    /// Write every finished run to a `runs` table of the SQLite database at this path, with its
+    /// number of rolls, duplicates and whether pity kicked in, replacing any earlier `runs` table
+    #[cfg(feature = "sqlite")]
+    #[arg(long, value_name = "PATH")]
+    sqlite: Option<PathBuf>,
+
+    /// Write the --dump-rolls file as the runs finish instead of all at once at the end if keeping
+    /// the rolls in memory could take more than this many megabytes
+    #[arg(long, value_name = "MB", requires = "dump_rolls")]
+    max_memory: Option<usize>,
+
+    /// Also write the summary statistics to this path in the Prometheus text exposition format,
+    /// so they can be scraped or pushed to a gateway
+    #[arg(long, value_name = "PATH")]
+    metrics: Option<PathBuf>,
+
+    /// Random number generator used to roll for prizes
+    #[arg(long, value_enum, default_value_t = RngKind::Thread)]
+    rng: RngKind,
+
+    /// Split the runs over this many threads, each with its own generator. With a seed, thread 0
+    /// rolls exactly what a single thread would, and the others get seeds 2^32 apart from it
+    #[arg(
+        long,
+        value_name = "N",
+        default_value = "1",
+        conflicts_with = "max_memory"
+    )]
+    threads: NonZeroUsize,
+
+    /// Relative chance of rolling each of the 8 prizes, e.g. `1,1,1,1,1,1,1,0.5`. Every prize is
+    /// equally likely by default
+    #[arg(long, value_name = "W1,...,W8")]
+    weights: Option<PrizeWeights>,
+
+    /// Read the prizes of the event from a TOML or JSON file: how many there are, how likely each
+    /// one is, how many copies of each are needed and the pity threshold, which --pity-threshold
+    /// overrides. Without an event there are 8 equally likely prizes
+    #[arg(
+        long,
+        value_name = "PATH",
+        value_parser = read_event_config,
+        conflicts_with_all = ["weights", "sets", "until", "deck_size", "blp_threshold"]
+    )]
+    event: Option<EventConfig>,
+
+    /// Bad luck protection for the rarest prize: after this many rolls in a row without the prize
+    /// with the lowest weight, the next roll grants it
+    #[arg(long, value_name = "N", requires = "weights")]
+    blp_threshold: Option<usize>,
+
+    /// Chance from 0 to 1 that a roll repeats the prize of the roll before it, to model events
+    /// with streaky outcomes. Rolls are independent by default
+    #[arg(long, value_name = "S", value_parser = parse_streakiness)]
+    streakiness: Option<f64>,
+
+    /// Draw the rolls without replacement from a shuffled deck of D tokens, spread as evenly as
+    /// possible over the prizes, like the numbers of a real bingo game. The deck is reshuffled
+    /// once it runs out
+    #[arg(long, value_name = "D", conflicts_with = "weights")]
+    deck_size: Option<usize>,
+
+    /// Give up on a run after this many rolls, like a player who runs out of patience. Abandoned
+    /// runs are counted separately and left out of the rest of the stats
+    #[arg(long, value_name = "N")]
+    give_up: Option<usize>,
+
+    /// Also report what the runs cost, with every roll costing this much currency. Stop early
+    /// on specific prizes with --until to budget for only those
+    #[arg(long, value_name = "C", value_parser = parse_cost)]
+    cost_per_roll: Option<f64>,
+
+    /// A discounted pack of rolls, e.g. "10:900" for 10 rolls for 900. Every run is charged the
+    /// cheapest mix of packs and single rolls that covers its rolls
+    #[arg(long, value_name = "ROLLS:PRICE", requires = "cost_per_roll")]
+    bundle: Option<Bundle>,
+
+    /// Seed the random number generator to get reproducible results. Experiments repeated with
+    /// --repeat, and the seeds of --compare-seeds, use consecutive seeds starting from this one
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Seed the random number generator with all 256 bits of its seed, written as 64 hex digits,
+    /// instead of a --seed number
+    #[arg(
+        long,
+        value_name = "HEX",
+        value_parser = parse_seed_hex,
+        conflicts_with = "seed"
+    )]
+    seed_hex: Option<Seed>,
+
+    /// Like --seed-hex, but read the hex digits from this file
+    #[arg(
+        long,
+        value_name = "PATH",
+        value_parser = read_seed_file,
+        conflicts_with_all = ["seed", "seed_hex"]
+    )]
+    seed_file: Option<Seed>,
+
+    /// Where to write the histogram. `{runs}`, `{prizes}`, `{pity}`, `{pity_mode}`, `{seed}`,
+    /// `{date}` and `{sweep}` are replaced with the number of runs, the number of prizes needed to
+    /// finish, the pity threshold, the pity mode, the seed, today's date and the --sweep value.
+    /// Missing directories are created
+    #[arg(
+        long,
+        short,
+        visible_alias = "output",
+        default_value = "output/{runs}-sim.png"
+    )]
+    output_template: String,
+
+    /// Fail instead of overwriting the histogram if its file already exists
+    #[arg(long, conflicts_with = "backup")]
+    no_clobber: bool,
+
+    /// Rename an existing histogram file by adding `.bak` to its name before writing the new one
+    #[arg(long)]
+    backup: bool,
+
+    /// Draw the histogram with a logarithmic y-axis, so that the rare long runs in the tail stay
+    /// visible
+    #[arg(long)]
+    log_y: bool,
+
+    /// Run everything once for each value of a parameter and finish with a table comparing them,
+    /// e.g. "pity=10,15,20,25". The parameter can be pity, give-up, streakiness or deck-size.
+    /// Each value gets its own histogram, with "-{sweep}" added to the --output-template if it
+    /// doesn't use it. Other output files are overwritten by each value in turn
+    #[arg(
+        long,
+        value_name = "PARAM=V1,V2,...",
+        value_parser = parse_sweep,
+        conflicts_with_all = [
+            "compare_seeds",
+            "compare_pity",
+            "session_size",
+            "remaining",
+            "solve_pity_for_mean",
+        ]
+    )]
+    sweep: Option<Sweep>,
+
+    /// Also draw the histograms of every --sweep value on one chart at this path, each as a line
+    /// in its own color
+    #[arg(long, value_name = "PATH", requires = "sweep")]
+    overlay: Option<PathBuf>,
+
+    /// The --sweep value the current run is for
+    #[arg(skip)]
+    sweep_value: Option<String>,
+
+    /// Leave the chart background transparent instead of filling it with white
+    #[arg(long)]
+    transparent: bool,
+
+    /// Render the charts in portrait at the aspect ratio of this page, with a blank border for
+    /// printing, instead of 16:9
+    #[arg(long, value_name = "PAGE")]
+    page: Option<PagePreset>,
+
+    /// Width of the rendered charts in pixels
+    #[arg(
+        long,
+        value_name = "PX",
+        value_parser = clap::value_parser!(u32).range(320..),
+        conflicts_with = "page"
+    )]
+    width: Option<u32>,
+
+    /// Height of the rendered charts in pixels
+    #[arg(
+        long,
+        value_name = "PX",
+        value_parser = clap::value_parser!(u32).range(180..),
+        conflicts_with = "page"
+    )]
+    height: Option<u32>,
+
+    /// Test the simulated results against the exact distribution of the number of rolls, which
+    /// assumes every prize is equally likely and every roll is independent
+    #[arg(long, conflicts_with_all = ["weights", "streakiness", "deck_size", "until", "event"])]
+    gof: bool,
+
+    /// Significance level of the --gof test
+    #[arg(long, default_value_t = 0.05, requires = "gof")]
+    alpha: f64,
+}
+
+impl Args {
+    /// The seed picked with --seed, --seed-hex or --seed-file, if any
+    fn effective_seed(&self) -> Option<Seed> {
+        self.seed
+            .map(Seed::Number)
+            .or(self.seed_hex)
+            .or(self.seed_file)
+    }
+
+    /// The size and border of the rendered charts
+    fn chart_layout(&self) -> ChartLayout {
+        match self.page {
+            Some(page) => ChartLayout {
+                size: page.chart_size(),
+                margin: PAGE_MARGIN,
+            },
+            None => ChartLayout {
+                size: (
+                    self.width.unwrap_or(CHART_SIZE.0),
+                    self.height.unwrap_or(CHART_SIZE.1),
+                ),
+                margin: 0,
+            },
+        }
+    }
+
+    /// How the histogram is laid out
+    fn histogram_options(&self) -> HistogramOptions {
+        HistogramOptions {
+            label_stride: self.label_stride.map(NonZeroUsize::get),
+            label_rotation: self.label_rotation,
+            tail_cap: self.tail_cap,
+            log_y: self.log_y,
+            min_rolls: Some(self.sim_config().min_rolls()),
+            event_name: self.event.as_ref().and_then(|event| event.name.clone()),
+            cost: self.cost_model(),
+        }
+    }
+
+    /// What the rolls cost, if --cost-per-roll is given
+    fn cost_model(&self) -> Option<CostModel> {
+        self.cost_per_roll.map(|cost_per_roll| CostModel {
+            cost_per_roll,
+            bundle: self.bundle,
+        })
+    }
+
+    /// How many prizes the simulated event has
+    fn num_prizes(&self) -> usize {
+        self.event.as_ref().map_or(NUM_PRIZES, |event| event.prizes)
+    }
+
+    /// How long to simulate and what to keep of the runs for the output asked for
+    fn simulate_options(&self) -> SimulateOptions {
+        SimulateOptions {
+            limit: match self.duration {
+                Some(duration) => RunLimit::Duration(Duration::from_secs_f64(duration)),
+                None => RunLimit::Runs(self.runs),
+            },
+            variance_reduction: self.variance_reduction,
+            first_earned: self.heatmap.is_some() || self.per_prize_by.is_some(),
+            collection_order: self.order_matrix.is_some(),
+            last_earned: self.prob_last.is_some(),
+            roll_sequences: self.dump_rolls.is_some(),
+            run_lengths: self.dump_lengths.is_some()
+                || matches!(self.runs_file, Some(RunsFile::Write(_))),
+            gaps: self.gaps || self.extended_stats,
+            autocorrelation: self.rng_diagnostics,
+            split_by_pity: self.split_by_pity,
+            condition_on: self.condition_on,
+            #[cfg(feature = "sqlite")]
+            run_records: self.sqlite.is_some(),
+        }
+    }
+
+    /// The simulation rules picked on the command line
+    fn sim_config(&self) -> SimConfig {
+        let mut config = SimConfig {
+            prizes: NUM_PRIZES,
+            pity_mode: self.pity_mode,
+            pity_threshold: self.pity_threshold.unwrap_or(PITY_THRESHOLD),
+            sets: self.sets.clone().unwrap_or_default(),
+            until: self.until.clone(),
+            give_up: self.give_up,
+            weights: self.weights.clone(),
+            blp_threshold: self.blp_threshold,
+            streakiness: self.streakiness.unwrap_or(0.),
+            deck_size: self.deck_size,
+            copies: vec![1; NUM_PRIZES],
+        };
+        if let Some(event) = &self.event {
+            event.apply(&mut config);
+            // The threshold picked on the command line or by a sweep wins over the event's
+            if let Some(pity_threshold) = self.pity_threshold {
+                config.pity_threshold = pity_threshold;
+            }
+        }
+        config
+    }
+}
+
+/// What a highlighted piece of the printed output is
+#[derive(Clone, Copy)]
+enum Highlight {
+    /// The most important number, like the average
+    Key,
+    Percentile,
+    Warning,
+}
+
+/// Wrap `text` in the ANSI codes for `highlight` if `enabled`
+#[cfg(feature = "color")]
+fn paint(text: impl fmt::Display, highlight: Highlight, enabled: bool) -> String {
+    use owo_colors::OwoColorize;
+
+    if !enabled {
+        return text.to_string();
+    }
+    match highlight {
+        Highlight::Key => text.bold().to_string(),
+        Highlight::Percentile => text.cyan().to_string(),
+        Highlight::Warning => text.yellow().to_string(),
+    }
+}
+
+/// Without the `color` feature there is nothing to paint with
+#[cfg(not(feature = "color"))]
+fn paint(text: impl fmt::Display, _highlight: Highlight, _enabled: bool) -> String {
+    text.to_string()
+}
+
+/// Whether to color output written to a stream that `is_terminal` or not
+fn color_enabled(args: &Args, is_terminal: bool) -> bool {
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|no_color| !no_color.is_empty());
+    cfg!(feature = "color") && !args.no_color && !no_color && is_terminal
+}
+
+/// Fill in the placeholders of an --output-template for a run of `runs` simulations
+fn expand_output_template(template: &str, args: &Args, runs: usize) -> Result<String, String> {
+    let mut expanded = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        expanded.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| format!("unclosed placeholder in output template '{}'", template))?
+            + start;
+        match &rest[start + 1..end] {
+            "runs" => expanded.push_str(&runs.to_string()),
+            "prizes" => {
+                let required = args.sim_config().required();
+                expanded.push_str(&required.iter().filter(|&&r| r).count().to_string())
+            }
+            "pity" => expanded.push_str(&args.sim_config().pity_threshold.to_string()),
+            "pity_mode" => expanded.push_str(&args.pity_mode.to_string()),
+            "seed" => match args.effective_seed() {
+                Some(seed) => expanded.push_str(&seed.to_string()),
+                None => expanded.push_str("random"),
+            },
+            "date" => expanded.push_str(&Local::now().format("%Y-%m-%d").to_string()),
+            "sweep" => expanded.push_str(args.sweep_value.as_deref().unwrap_or("none")),
+            placeholder => {
+                return Err(format!(
+                    "unknown placeholder '{{{}}}' in output template",
+                    placeholder
+                ))
+            }
+        }
+        rest = &rest[end + 1..];
+    }
+    expanded.push_str(rest);
+
+    Ok(expanded)
+}
+
+/// Deal with a file that already exists where an output is about to be written, as asked for with
+/// --no-clobber or --backup. By default it is simply overwritten
+fn make_room_for_output(path: &Path, args: &Args) -> Result<(), AppError> {
+    if !path.exists() {
+        return Ok(());
+    }
+    if args.no_clobber {
+        return Err(AppError::Io(
+            format!(
+                "{} already exists and --no-clobber was given",
+                path.display()
+            )
+            .into(),
+        ));
+    }
+    if args.backup {
+        let mut backup_path = path.as_os_str().to_owned();
+        backup_path.push(".bak");
+        fs::rename(path, backup_path)?;
+    }
+
+    Ok(())
+}
+
+/// A parameter that --sweep can vary
+#[derive(Clone, Copy, Debug)]
+enum SweepParameter {
+    Pity,
+    GiveUp,
+    Streakiness,
+    DeckSize,
+}
+
+impl fmt::Display for SweepParameter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SweepParameter::Pity => write!(f, "pity"),
+            SweepParameter::GiveUp => write!(f, "give-up"),
+            SweepParameter::Streakiness => write!(f, "streakiness"),
+            SweepParameter::DeckSize => write!(f, "deck-size"),
+        }
+    }
+}
+
+/// The values to run a --sweep with
+#[derive(Clone, Debug)]
+struct Sweep {
+    parameter: SweepParameter,
+    /// Checked to be valid for the parameter when parsing
+    values: Vec<String>,
+}
+
+impl Sweep {
+    /// `args` with the swept parameter set to `value`
+    fn apply(&self, args: &Args, value: &str) -> Args {
+        let mut args = Args {
+            sweep: None,
+            sweep_value: Some(value.to_string()),
+            ..args.clone()
+        };
+        // okay to unwrap because parse_sweep checked every value
+        match self.parameter {
+            SweepParameter::Pity => args.pity_threshold = Some(value.parse().unwrap()),
+            SweepParameter::GiveUp => args.give_up = Some(value.parse().unwrap()),
+            SweepParameter::Streakiness => args.streakiness = Some(value.parse().unwrap()),
+            SweepParameter::DeckSize => args.deck_size = Some(value.parse().unwrap()),
+        }
+        if !args.output_template.contains("{sweep}") {
+            args.output_template = insert_before_extension(
+                &args.output_template,
+                &format!("-{}-{{sweep}}", self.parameter),
+            );
+        }
+
+        args
+    }
+}
+
+/// Parse a --sweep like "pity=10,15,20,25"
+fn parse_sweep(s: &str) -> Result<Sweep, String> {
+    let (parameter, values) = s
+        .split_once('=')
+        .ok_or_else(|| "expected PARAM=V1,V2,..., e.g. pity=10,15,20,25".to_string())?;
+    let parameter = match parameter {
+        "pity" => SweepParameter::Pity,
+        "give-up" => SweepParameter::GiveUp,
+        "streakiness" => SweepParameter::Streakiness,
+        "deck-size" => SweepParameter::DeckSize,
+        _ => {
+            return Err(format!(
+                "can't sweep '{}', expected pity, give-up, streakiness or deck-size",
+                parameter
+            ))
+        }
+    };
+    let values: Vec<String> = values
+        .split(',')
+        .map(|value| value.trim().to_string())
+        .collect();
+    for value in &values {
+        match parameter {
+            SweepParameter::Streakiness => parse_streakiness(value).map(|_| ()),
+            SweepParameter::Pity | SweepParameter::GiveUp | SweepParameter::DeckSize => value
+                .parse::<usize>()
+                .map(|_| ())
+                .map_err(|err| format!("invalid {} '{}': {}", parameter, value, err)),
+        }?;
+    }
+
+    Ok(Sweep { parameter, values })
+}
+
+/// A seed for the seedable random number generators
+#[derive(Clone, Copy, Debug)]
+enum Seed {
+    /// Expanded into a full seed by the generator's own `seed_from_u64`
+    Number(u64),
+    /// Every byte of the seed, copied straight into the generator's seed
+    Full([u8; SEED_BYTES]),
+}
+
+/// How many bytes of a full --seed-hex there are, enough for both seedable generators
+const SEED_BYTES: usize = 32;
+
+impl Seed {
+    /// Create a generator of type `R` from this seed. A full seed is repeated or cut short if
+    /// `R`'s seed isn't exactly `SEED_BYTES` long
+    fn rng<R: SeedableRng>(self) -> R {
+        match self {
+            Seed::Number(seed) => R::seed_from_u64(seed),
+            Seed::Full(bytes) => {
+                let mut seed = R::Seed::default();
+                for (byte, &seed_byte) in seed.as_mut().iter_mut().zip(bytes.iter().cycle()) {
+                    *byte = seed_byte;
+                }
+                R::from_seed(seed)
+            }
+        }
+    }
+
+    /// The seed `n` after this one, treating a full seed as a little-endian number
+    fn offset(self, n: u64) -> Seed {
+        match self {
+            Seed::Number(seed) => Seed::Number(seed.wrapping_add(n)),
+            Seed::Full(mut bytes) => {
+                let mut carry = n;
+                for byte in &mut bytes {
+                    let sum = *byte as u64 + (carry & 0xff);
+                    *byte = sum as u8;
+                    carry = (carry >> 8) + (sum >> 8);
+                }
+                Seed::Full(bytes)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Seed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Seed::Number(seed) => write!(f, "{}", seed),
+            Seed::Full(bytes) => bytes.iter().try_for_each(|byte| write!(f, "{:02x}", byte)),
+        }
+    }
+}
+
+/// Parse a --seed-hex, which has to spell out every byte of the seed
+fn parse_seed_hex(s: &str) -> Result<Seed, String> {
+    let s = s.strip_prefix("0x").unwrap_or(s);
+    if let Some(c) = s.chars().find(|c| !c.is_ascii_hexdigit()) {
+        return Err(format!("'{}' in the seed isn't a hex digit", c));
+    }
+    if s.len() != SEED_BYTES * 2 {
+        return Err(format!(
+            "the seed needs exactly {} hex digits, got {}",
+            SEED_BYTES * 2,
+            s.len()
+        ));
+    }
+    let mut bytes = [0; SEED_BYTES];
+    for (byte_idx, byte) in bytes.iter_mut().enumerate() {
+        // okay to unwrap because the digits were checked above
+        *byte = u8::from_str_radix(&s[byte_idx * 2..byte_idx * 2 + 2], 16).unwrap();
+    }
+
+    Ok(Seed::Full(bytes))
+}
+
+/// Read a --seed-file, which holds the same hex digits as a --seed-hex
+fn read_seed_file(path: &str) -> Result<Seed, String> {
+    let contents =
+        fs::read_to_string(path).map_err(|err| format!("couldn't read {}: {}", path, err))?;
+    parse_seed_hex(contents.trim())
+}
+
+/// Parse a --streakiness, which is a probability
+fn parse_streakiness(s: &str) -> Result<f64, String> {
+    match s.parse() {
+        Ok(streakiness) if (0. ..=1.).contains(&streakiness) => Ok(streakiness),
+        Ok(_) => Err("the streakiness has to be between 0 and 1".to_string()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// Parse a --cost-per-roll, which can be any non-negative amount
+fn parse_cost(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(cost) if cost.is_finite() && cost >= 0. => Ok(cost),
+        Ok(_) => Err("the cost has to be a non-negative number".to_string()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// Parse a --budget percentage, which has to be above 0 and at most 100
+fn parse_budget(s: &str) -> Result<f64, String> {
+    match s.parse() {
+        Ok(budget) if budget > 0. && budget <= 100. => Ok(budget),
+        Ok(_) => Err("the budget has to be above 0 and at most 100 percent".to_string()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// How far a run in progress has got, as given to --remaining
+#[derive(Clone, Debug)]
+struct Progress {
+    /// Whether each prize has been earned. How many prizes there are depends on the --event, so
+    /// `check_options` checks that it fits
+    earned_prizes: Vec<bool>,
+    rolls: usize,
+}
+
+/// Read the --event file up front, so that a broken file is reported like any invalid option
+fn read_event_config(path: &str) -> Result<EventConfig, String> {
+    EventConfig::from_path(Path::new(path))
+}
+
+/// Parse a --condition-on event like "duplicate-within:4" or "prizes-within:10:6"
+fn parse_early_event(s: &str) -> Result<EarlyEvent, String> {
+    let parse_count = |count: &str| {
+        count
+            .parse::<usize>()
+            .map_err(|err| format!("invalid number '{}': {}", count, err))
+    };
+    match s.split(':').collect::<Vec<_>>()[..] {
+        ["duplicate-within", rolls] => Ok(EarlyEvent::DuplicateWithin(parse_count(rolls)?)),
+        ["prizes-within", rolls, prizes] => Ok(EarlyEvent::PrizesWithin {
+            rolls: parse_count(rolls)?,
+            prizes: parse_count(prizes)?,
+        }),
+        _ => Err("expected duplicate-within:K or prizes-within:K:N".to_string()),
+    }
+}
+
+/// Parse a --remaining progress like "01101000:12"
+fn parse_progress(s: &str) -> Result<Progress, String> {
+    let (mask, rolls) = s
+        .split_once(':')
+        .ok_or_else(|| "expected MASK:ROLLS, e.g. 01101000:12".to_string())?;
+    let earned_prizes = mask
+        .chars()
+        .map(|c| match c {
+            '0' => Ok(false),
+            '1' => Ok(true),
+            _ => Err(format!("unexpected '{}' in the mask, expected 0 or 1", c)),
+        })
+        .collect::<Result<_, _>>()?;
+    let rolls = rolls
+        .parse()
+        .map_err(|err| format!("invalid number of rolls '{}': {}", rolls, err))?;
+
+    Ok(Progress {
+        earned_prizes,
+        rolls,
+    })
+}
+
+/// Parse a count that has to be at least 1, like --runs, so that there is always something to
+/// analyze. Big counts can be written in scientific notation, like `1e6`
+fn parse_positive(s: &str) -> Result<usize, String> {
+    let count = match s.parse::<usize>() {
+        Ok(count) => count,
+        Err(err) => match s.parse::<f64>() {
+            Ok(count) if count.fract() == 0. && (0. ..=usize::MAX as f64).contains(&count) => {
+                count as usize
+            }
+            Ok(_) => return Err(format!("'{}' is not a whole number", s)),
+            Err(_) => return Err(err.to_string()),
+        },
+    };
+    match count {
+        0 => Err("has to be at least 1".to_string()),
+        count => Ok(count),
+    }
+}
+
+/// Parse a prize numbered from 1, keeping it numbered from 1. How many prizes there are depends
+/// on the --event, so `check_options` checks that it is one of them
+fn parse_prize_number(s: &str) -> Result<usize, String> {
+    match s.parse() {
+        Ok(0) => Err("the prizes are numbered from 1".to_string()),
+        Ok(prize) => Ok(prize),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// The random number generator algorithms that can drive the simulation
+#[derive(Clone, Copy, ValueEnum)]
+enum RngKind {
+    /// `SmallRng`, the fastest option. It isn't cryptographically secure, but its quality is more
+    /// than good enough for rolling prizes
+    Small,
+    /// `StdRng`, a cryptographically secure generator seeded once from the OS
+    Std,
+    /// `ThreadRng`, the thread-local cryptographically secure generator that is periodically
+    /// reseeded from the OS
+    Thread,
+}
+
+/// Pixel dimensions of every rendered chart, unless a --page is given
+const CHART_SIZE: (u32, u32) = (1280, 720);
+
+/// Print page shapes for --page, all in portrait
+#[derive(Clone, Copy, ValueEnum)]
+enum PagePreset {
+    /// 210 x 297 mm
+    A4,
+    /// 8.5 x 11 inches
+    Letter,
+    Square,
+}
+
+impl PagePreset {
+    /// The pixel dimensions of a chart for the page. They keep the width of [`CHART_SIZE`] so that
+    /// the text comes out as legible as on the default chart, and only grow the height
+    fn chart_size(self) -> (u32, u32) {
+        let (width, _) = CHART_SIZE;
+        match self {
+            PagePreset::A4 => (width, width * 297 / 210),
+            PagePreset::Letter => (width, width * 110 / 85),
+            PagePreset::Square => (width, width),
+        }
+    }
+}
+
+/// The blank border kept around a chart rendered for a --page, in pixels
+const PAGE_MARGIN: u32 = 64;
+
+/// How big the rendered charts are and how much blank border they get
+#[derive(Clone, Copy)]
+struct ChartLayout {
+    size: (u32, u32),
+    margin: u32,
+}
+
+/// Why the simulator stopped without finishing, each with its own exit status for scripts
+#[derive(Debug)]
+enum AppError {
+    /// The options don't make sense together. Exits with 2, like clap's own usage errors
+    Config(String),
+    /// An output file couldn't be written. Exits with 3
+    Io(Box<dyn std::error::Error>),
+    /// Stopped early with Ctrl-C, after writing whatever could be written. Exits with 130
+    /// (128 + SIGINT)
+    Interrupted,
+    /// This many of the --self-test checks failed. Exits with 1
+    SelfTestFailed(usize),
+}
+
+impl AppError {
+    fn exit_code(&self) -> i32 {
+        match self {
+            AppError::Config(_) => 2,
+            AppError::Io(_) => 3,
+            AppError::Interrupted => 130,
+            AppError::SelfTestFailed(_) => 1,
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::Config(message) => write!(f, "{}", message),
+            AppError::Io(err) => write!(f, "couldn't write the output: {}", err),
+            AppError::Interrupted => write!(f, "interrupted"),
+            AppError::SelfTestFailed(failed) => {
+                write!(f, "{} of the self-test checks failed", failed)
+            }
+        }
+    }
+}
+
+impl From<Box<dyn std::error::Error>> for AppError {
+    fn from(err: Box<dyn std::error::Error>) -> Self {
+        AppError::Io(err)
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(err: std::io::Error) -> Self {
+        AppError::Io(err.into())
+    }
+}
+
+impl From<CompareError> for AppError {
+    fn from(err: CompareError) -> Self {
+        match err {
+            CompareError::Io(err) => err.into(),
+            err => AppError::Config(err.to_string()),
+        }
+    }
+}
+
+impl From<serde_json::Error> for AppError {
+    fn from(err: serde_json::Error) -> Self {
+        AppError::Io(err.into())
+    }
+}
+
+/// The summary statistics of the finished runs in `hist_data`, or an error rather than a panic
+/// when none of the runs of `what` finished
+fn summarize_finished(
+    hist_data: &HashMap<usize, usize>,
+    percentiles: &[f64],
+    what: &str,
+) -> Result<StatsSummary, AppError> {
+    if hist_data.values().all(|&count| count == 0) {
+        return Err(AppError::Config(format!(
+            "no run of {} finished, so there is nothing to analyze",
+            what
+        )));
+    }
+    Ok(analyze_histogram(hist_data, percentiles))
+}
+
+/// Render a chart to a PNG at `path` with `draw`, inside the border of `layout`. Drawn on white
+/// unless `transparent` is set.
+///
+/// The bitmap backend has no alpha channel, so transparent charts are drawn twice, once on black
+/// and once on white, and the alpha of each pixel is recovered from how much it differs between
+/// the two.
+fn render_png<F>(
+    path: &Path,
+    layout: ChartLayout,
+    transparent: bool,
+    draw: F,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    F: Fn(&DrawingArea<BitMapBackend, Shift>) -> Result<(), Box<dyn std::error::Error>>,
+{
+    let (width, height) = layout.size;
+    let margin = layout.margin;
+    if !transparent {
+        let root = BitMapBackend::new(path, layout.size).into_drawing_area();
+        root.fill(&WHITE)?;
+        draw(&root.margin(margin, margin, margin, margin))?;
+        root.present()?;
+        return Ok(());
+    }
+
+    let render_on = |background: &RGBColor| -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut buffer = vec![0; (width * height * 3) as usize];
+        {
+            let root = BitMapBackend::with_buffer(&mut buffer, layout.size).into_drawing_area();
+            root.fill(background)?;
+            draw(&root.margin(margin, margin, margin, margin))?;
+            root.present()?;
+        }
+        Ok(buffer)
+    };
+    let on_black = render_on(&BLACK)?;
+    let on_white = render_on(&WHITE)?;
+
+    let mut rgba = Vec::with_capacity((width * height * 4) as usize);
+    for (black_px, white_px) in on_black.chunks(3).zip(on_white.chunks(3)) {
+        // A pixel with opacity a and color c shows up as a * c on black and a * c + (1 - a) on
+        // white, so the difference between the two is 1 - a
+        let alpha = 255 - white_px[0].saturating_sub(black_px[0]);
+        for &channel in black_px {
+            rgba.push(if alpha == 0 {
+                0
+            } else {
+                (channel as u32 * 255 / alpha as u32).min(255) as u8
+            });
+        }
+        rgba.push(alpha);
+    }
+    image::save_buffer(path, &rgba, width, height, image::ColorType::Rgba8)?;
+
+    Ok(())
+}
+
+/// Write an HTML page with the histogram as an inline SVG followed by tables of the summary
+/// statistics and the histogram
+fn write_html_report(
+    path: &Path,
+    summary: &StatsSummary,
+    precision: usize,
+    layout: ChartLayout,
+    transparent: bool,
+    options: &HistogramOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut svg = String::new();
+    {
+        let root = SVGBackend::with_string(&mut svg, layout.size).into_drawing_area();
+        if !transparent {
+            root.fill(&WHITE)?;
+        }
+        let hist_data = summary.histogram.iter().map(|(&k, &v)| (k, v)).collect();
+        let margin = layout.margin;
+        draw_histogram_into(
+            &root.margin(margin, margin, margin, margin),
+            &hist_data,
+            options,
+        )?;
+        root.present()?;
+    }
+
+    let title = format!(
+        "Result of {} simulations of {}",
+        summary.runs,
+        escape_html(options.event_name.as_deref().unwrap_or("uma-bingo"))
+    );
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(
+        writer,
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<style>
+body {{ font-family: Calibri, sans-serif; margin: 2em; }}
+svg {{ max-width: 100%; height: auto; }}
+table {{ border-collapse: collapse; margin-bottom: 2em; }}
+th, td {{ border: 1px solid #ccc; padding: 0.25em 0.75em; text-align: right; }}
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+{svg}
+<h2>Summary</h2>
+<table>
+<tr><th>Mean</th><td>{mean:.precision$}</td></tr>
+<tr><th>Standard deviation</th><td>{std_dev:.precision$}</td></tr>
+<tr><th>Min</th><td>{min}</td></tr>
+<tr><th>Max</th><td>{max}</td></tr>
+<tr><th>Median</th><td>{median}</td></tr>
+<tr><th>Mode</th><td>{mode}</td></tr>"#,
+        title = title,
+        svg = svg,
+        mean = summary.mean,
+        std_dev = summary.std_dev,
+        min = summary.min,
+        max = summary.max,
+        median = summary.median,
+        mode = summary.mode,
+        precision = precision,
+    )?;
+    for percentile in &summary.percentiles {
+        writeln!(
+            writer,
+            "<tr><th>{}th percentile</th><td>{}</td></tr>",
+            percentile.percentile, percentile.rolls
+        )?;
+    }
+    writeln!(
+        writer,
+        "</table>\n<h2>Histogram</h2>\n<table>\n<tr><th>Rolls</th><th>Runs</th><th>Share</th></tr>"
+    )?;
+    for (rolls, count) in &summary.histogram {
+        writeln!(
+            writer,
+            "<tr><td>{}</td><td>{}</td><td>{:.*}%</td></tr>",
+            rolls,
+            count,
+            precision,
+            (*count as f64 / summary.runs as f64) * 100.
+        )?;
+    }
+    writeln!(writer, "</table>\n</body>\n</html>")?;
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// Replace the characters that HTML would read as markup in `text` with their entities
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Run the configured simulation once per pity mode from the same seed, then compare their stats
+/// and chart their histograms together
+fn print_pity_comparison(
+    args: &Args,
+    pity_modes: &[PityMode],
+    interrupted: &AtomicBool,
+) -> Result<(), AppError> {
+    // Every mode starts from the same seed, so without one pick one for all of them
+    let seed = args
+        .effective_seed()
+        .unwrap_or_else(|| Seed::Number(rand::random()));
+    let simulator = Simulator::from(args.sim_config());
+    let options = args.simulate_options();
+    let comparison = match args.rng {
+        RngKind::Small => simulator.compare_pity(
+            pity_modes,
+            &options,
+            &args.percentiles,
+            || seed.rng::<SmallRng>(),
+            interrupted,
+        )?,
+        RngKind::Std => simulator.compare_pity(
+            pity_modes,
+            &options,
+            &args.percentiles,
+            || seed.rng::<StdRng>(),
+            interrupted,
+        )?,
+        RngKind::Thread => {
+            return Err(AppError::Config(
+                "--compare-pity needs a seedable --rng (small or std)".to_string(),
+            ))
+        }
+    };
+    if interrupted.load(Ordering::Relaxed) {
+        println!(
+            "Interrupted after {} of {} pity modes",
+            comparison.len(),
+            pity_modes.len()
+        );
+        return Err(AppError::Interrupted);
+    }
+
+    let scenarios: Vec<_> = comparison
+        .iter()
+        .map(|((name, _), summary)| (name.clone(), summary))
+        .collect();
+    print_comparison_table(&scenarios, args.precision, args.pretty);
+
+    let suffix = format!(
+        "-pity-{}",
+        pity_modes
+            .iter()
+            .map(PityMode::to_string)
+            .collect::<Vec<_>>()
+            .join("-")
+    );
+    let path = prepare_output(args, args.runs, &suffix)?;
+    let histograms: Vec<_> = comparison
+        .into_iter()
+        .map(|(histogram, _)| histogram)
+        .collect();
+    render_png(&path, args.chart_layout(), args.transparent, |root| {
+        draw_overlaid_histograms(
+            root,
+            &histograms,
+            &format!("{} simulations of uma-bingo with each pity mode", args.runs),
+        )
+    })?;
+
+    Ok(())
+}
+
+/// Run the configured simulation once per seed and chart how the histograms differ
+fn chart_seed_comparison(
+    args: &Args,
+    seeds: u64,
+    interrupted: &AtomicBool,
+) -> Result<(), AppError> {
+    // The seeds count up from the one picked on the command line, or from 0 without one
+    let first_seed = args.effective_seed().unwrap_or(Seed::Number(0));
+    let simulator = Simulator::from(args.sim_config());
+    let options = args.simulate_options();
+    let histograms = match args.rng {
+        RngKind::Small => simulator.compare_seeds(
+            seeds,
+            &options,
+            |seed_idx| first_seed.offset(seed_idx).rng::<SmallRng>(),
+            interrupted,
+        )?,
+        RngKind::Std => simulator.compare_seeds(
+            seeds,
+            &options,
+            |seed_idx| first_seed.offset(seed_idx).rng::<StdRng>(),
+            interrupted,
+        )?,
+        RngKind::Thread => {
+            return Err(AppError::Config(
+                "--compare-seeds needs a seedable --rng (small or std)".to_string(),
+            ))
+        }
+    };
+
+    if !histograms.is_empty() {
+        let path = prepare_output(args, args.runs, &format!("-{}-seeds", histograms.len()))?;
+        render_png(&path, args.chart_layout(), args.transparent, |root| {
+            draw_seed_comparison(root, &histograms, args.runs)
+        })?;
+    }
+
+    if interrupted.load(Ordering::Relaxed) {
+        println!("Interrupted after {} of {} seeds", histograms.len(), seeds);
+        return Err(AppError::Interrupted);
+    }
+
+    Ok(())
+}
+
+/// Report the distribution of new prizes earned by a session of `pulls` pulls
+fn print_session_stats(
+    args: &Args,
+    pulls: usize,
+    interrupted: &AtomicBool,
+) -> Result<(), AppError> {
+    let simulator = Simulator::from(args.sim_config());
+    let session_stats = match (args.rng, args.effective_seed()) {
+        (RngKind::Small, Some(seed)) => {
+            simulator.session_stats(pulls, args.runs, &mut seed.rng::<SmallRng>(), interrupted)
+        }
+        (RngKind::Small, None) => {
+            simulator.session_stats(pulls, args.runs, &mut SmallRng::from_os_rng(), interrupted)
+        }
+        (RngKind::Std, Some(seed)) => {
+            simulator.session_stats(pulls, args.runs, &mut seed.rng::<StdRng>(), interrupted)
+        }
+        (RngKind::Std, None) => {
+            simulator.session_stats(pulls, args.runs, &mut StdRng::from_os_rng(), interrupted)
+        }
+        (RngKind::Thread, _) => {
+            simulator.session_stats(pulls, args.runs, &mut rand::rng(), interrupted)
+        }
+    };
+    let sessions = session_stats.sessions();
+
+    if interrupted.load(Ordering::Relaxed) {
+        println!("Interrupted after {} of {} sessions", sessions, args.runs);
+    }
+    if let Some(mean_new_prizes) = session_stats.mean_new_prizes() {
+        println!(
+            "Average number of new prizes from a {}-pull session: {:.*}",
+            pulls, args.precision, mean_new_prizes
+        );
+        if args.pretty {
+            let mut table = new_table(["New prizes", "Sessions", "Share"]);
+            for (new_prizes, count) in session_stats.new_prize_counts.iter().enumerate() {
+                table.add_row([
+                    new_prizes.to_string(),
+                    count.to_string(),
+                    format!(
+                        "{:.*}%",
+                        args.precision,
+                        (*count as f64 / sessions as f64) * 100.
+                    ),
+                ]);
+            }
+            align_right(&mut table, &[0, 1, 2]);
+            println!("{}", table);
+        } else {
+            for (new_prizes, count) in session_stats.new_prize_counts.iter().enumerate() {
+                println!(
+                    "{}: {} ({:.*}%)",
+                    new_prizes,
+                    count,
+                    args.precision,
+                    (*count as f64 / sessions as f64) * 100.
+                );
+            }
+        }
+    }
+
+    if interrupted.load(Ordering::Relaxed) {
+        return Err(AppError::Interrupted);
+    }
+
+    Ok(())
+}
+
+/// Report the pity threshold whose average number of rolls is closest to `target_mean`
+///
+/// The thread generator can't be seeded the same way for every threshold, so --rng thread uses
+/// the small generator instead.
+fn print_solved_pity(
+    args: &Args,
+    target_mean: f64,
+    interrupted: &AtomicBool,
+) -> Result<(), AppError> {
+    if args.pity_mode == PityMode::None {
+        return Err(AppError::Config(
+            "--solve-pity-for-mean needs a pity mode other than none".to_string(),
+        ));
+    }
+    let seed = args
+        .effective_seed()
+        .unwrap_or_else(|| Seed::Number(rand::rng().random()));
+    let simulator = Simulator::from(args.sim_config());
+    let solution = match args.rng {
+        RngKind::Std => simulator.solve_pity_for_mean(
+            args.runs,
+            target_mean,
+            || seed.rng::<StdRng>(),
+            interrupted,
+        )?,
+        RngKind::Small | RngKind::Thread => simulator.solve_pity_for_mean(
+            args.runs,
+            target_mean,
+            || seed.rng::<SmallRng>(),
+            interrupted,
+        )?,
+    };
+    let Some(PitySolution {
+        pity_threshold,
+        mean,
+        reaches_target,
+    }) = solution
+    else {
+        return Err(AppError::Interrupted);
+    };
+
+    if reaches_target {
+        println!(
+            "A pity threshold of {} gives an average of {:.*} rolls",
+            pity_threshold, args.precision, mean
+        );
+    } else {
+        println!(
+            "No pity threshold gets an average of {:.*} rolls, the closest is {} with an average of {:.*}",
+            args.precision, target_mean, pity_threshold, args.precision, mean
+        );
+    }
+
+    Ok(())
+}
+
+/// Report the average number of rolls left from a run in progress
+fn remaining_rolls(args: &Args, progress: &Progress) -> Result<(), AppError> {
+    let config = args.sim_config();
+    let Progress {
+        earned_prizes,
+        rolls,
+    } = progress;
+    let rolls = *rolls;
+    let remaining = match (args.rng, args.effective_seed()) {
+        (RngKind::Small, Some(seed)) => expected_remaining_rolls(
+            &config,
+            earned_prizes,
+            rolls,
+            args.runs,
+            &mut seed.rng::<SmallRng>(),
+        ),
+        (RngKind::Small, None) => expected_remaining_rolls(
+            &config,
+            earned_prizes,
+            rolls,
+            args.runs,
+            &mut SmallRng::from_os_rng(),
+        ),
+        (RngKind::Std, Some(seed)) => expected_remaining_rolls(
+            &config,
+            earned_prizes,
+            rolls,
+            args.runs,
+            &mut seed.rng::<StdRng>(),
+        ),
+        (RngKind::Std, None) => expected_remaining_rolls(
+            &config,
+            earned_prizes,
+            rolls,
+            args.runs,
+            &mut StdRng::from_os_rng(),
+        ),
+        (RngKind::Thread, _) => {
+            expected_remaining_rolls(&config, earned_prizes, rolls, args.runs, &mut rand::rng())
+        }
+    };
+    println!(
+        "Average number of rolls left with {} of {} prizes after {} rolls: {:.*}",
+        earned_prizes.iter().filter(|&&earned| earned).count(),
+        config.prizes,
+        rolls,
+        args.precision,
+        remaining
+    );
+
+    Ok(())
+}
+
+/// How many runs each --self-test check simulates
+const SELF_TEST_RUNS: usize = 100_000;
+
+/// Run the --self-test checks with the default rules and fixed seeds, so that they pass or fail
+/// the same way every time on a working build
+fn self_test() -> Result<(), AppError> {
+    let config = SimConfig::default();
+    let mut checks = Vec::new();
+
+    let reproducible = (0..100).all(|seed| {
+        run_sim(&config, &mut SmallRng::seed_from_u64(seed))
+            == run_sim(&config, &mut SmallRng::seed_from_u64(seed))
+            && run_sim(&config, &mut StdRng::seed_from_u64(seed))
+                == run_sim(&config, &mut StdRng::seed_from_u64(seed))
+    });
+    checks.push((
+        "runs with the same seed roll the same prizes".to_string(),
+        reproducible,
+    ));
+
+    // Every roll past the threshold earns a new prize, and at least one was earned before it
+    let max_rolls = config.pity_threshold + config.prizes - 1;
+    let mut rng = SmallRng::seed_from_u64(0);
+    let mut hist_data = HashMap::new();
+    for _ in 0..SELF_TEST_RUNS {
+        *hist_data
+            .entry(run_sim(&config, &mut rng).len())
+            .or_insert(0) += 1;
+    }
+    checks.push((
+        format!(
+            "every run takes between {} and {} rolls",
+            config.prizes, max_rolls
+        ),
+        hist_data
+            .keys()
+            .all(|&rolls| (config.prizes..=max_rolls).contains(&rolls)),
+    ));
+
+    let summary = analyze_histogram(&hist_data, &[]);
+    checks.push((
+        "the histogram counts every run once".to_string(),
+        summary.runs == SELF_TEST_RUNS
+            && summary.histogram.values().sum::<usize>() == SELF_TEST_RUNS,
+    ));
+
+    // Four standard errors keep the check from failing by chance on a working build
+    let exact_mean = exact_mean_rolls(config.prizes, config.prizes, Some(config.pity_threshold));
+    checks.push((
+        "the average is within 4 standard errors of the exact expectation".to_string(),
+        (summary.mean - exact_mean).abs() <= 4. * summary.standard_error(),
+    ));
+
+    for (description, passed) in &checks {
+        println!("{}: {}", if *passed { "pass" } else { "FAIL" }, description);
+    }
+    match checks.iter().filter(|(_, passed)| !passed).count() {
+        0 => Ok(()),
+        failed => Err(AppError::SelfTestFailed(failed)),
+    }
+}
+
+/// The fraction of `runs` runs that had earned each of `prizes` prizes within the first `rolls`
+/// rolls
+fn earned_within(
+    first_earned: &[Vec<usize>],
+    prizes: usize,
+    runs: usize,
+    rolls: usize,
+) -> Vec<f64> {
+    let mut earned = vec![0; prizes];
+    for counts in first_earned.iter().take(rolls) {
+        for (earned, count) in earned.iter_mut().zip(counts) {
+            *earned += count;
+        }
+    }
+
+    earned
+        .into_iter()
+        .map(|earned| earned as f64 / runs as f64)
+        .collect()
+}
+
+/// Write the collection order matrix as a CSV with one row per prize and one column per position
+fn write_order_matrix(path: &Path, collection_order: &[Vec<usize>]) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write!(writer, "prize")?;
+    for position in 1..=collection_order.len() {
+        write!(writer, ",{}", position)?;
+    }
+    writeln!(writer)?;
+
+    for (prize_idx, counts) in collection_order.iter().enumerate() {
+        write!(writer, "{}", prize_idx + 1)?;
+        for count in counts {
+            write!(writer, ",{}", count)?;
+        }
+        writeln!(writer)?;
+    }
+
+    writer.flush()
+}
+
+/// Above this many runs, warn that --dump-rolls keeps a lot of rolls in memory
+const DUMP_ROLLS_WARNING_RUNS: usize = 1_000_000;
+
+/// Roughly how much memory --dump-rolls takes per run to keep its rolls until the end
+const ROLL_SEQUENCE_BYTES: usize = 50;
+
+/// Estimate how many bytes the options that keep something for every run in memory will need,
+/// which is only --dump-rolls. Returns `None` when that depends on how many runs a --duration
+/// gets through
+fn retained_memory_estimate(args: &Args) -> Option<usize> {
+    if args.dump_rolls.is_none() {
+        return Some(0);
+    }
+    if args.duration.is_some() {
+        return None;
+    }
+    Some(args.runs * args.repeat * ROLL_SEQUENCE_BYTES)
+}
+
+/// Whether --max-memory makes --dump-rolls write the rolls out as the runs finish, which it does
+/// when keeping them could take more than that or when there's no telling how much they'll take
+fn streams_rolls(args: &Args) -> bool {
+    args.max_memory.is_some_and(|max_memory| {
+        retained_memory_estimate(args).is_none_or(|bytes| bytes > max_memory * 1024 * 1024)
+    })
+}
+
+/// Read the runs of a --dump-rolls file of an event with `prizes` prizes, one run per line
+fn read_roll_sequences(path: &Path, prizes: usize) -> Result<Vec<Vec<RollResult>>, AppError> {
+    let contents = fs::read_to_string(path)
+        .map_err(|err| AppError::Config(format!("couldn't read '{}': {}", path.display(), err)))?;
+    contents
+        .lines()
+        .enumerate()
+        .map(|(line_idx, line)| {
+            line.split_whitespace()
+                .map(|index| {
+                    index
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|&index| index < prizes)
+                        .map(RollResult::from)
+                        .ok_or_else(|| {
+                            AppError::Config(format!(
+                                "line {} of '{}': '{}' is not a prize index, expected 0 to {}",
+                                line_idx + 1,
+                                path.display(),
+                                index,
+                                prizes - 1
+                            ))
+                        })
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Print the stats of the runs in the --dump-lengths file at `path` and chart them
+fn load_lengths(args: &Args, path: &Path) -> Result<(), AppError> {
+    let lengths = read_lengths_file(args, path)?;
+    let hist_data = lengths_histogram(&lengths, path)?;
+    print_lengths_summary(args, &hist_data);
+    write_histogram(args, &hist_data, lengths.len(), &args.histogram_options())
+}
+
+/// Simulate the runs for `simulate` and write the number of rolls of every finished one to `path`
+fn write_runs_file(args: &Args, path: &Path, interrupted: &AtomicBool) -> Result<(), AppError> {
+    let seed = args.effective_seed();
+    let aggregate = if args.threads.get() == 1 {
+        simulate_seeded(args, seed, None, interrupted)?
+    } else {
+        simulate_in_parallel(args, seed, interrupted)?
+    };
+    if interrupted.load(Ordering::SeqCst) {
+        match args.duration {
+            Some(_) => println!("Interrupted after {} runs", aggregate.completed),
+            None => println!(
+                "Interrupted after {} of {} runs",
+                aggregate.completed, args.runs
+            ),
+        }
+        if aggregate.run_lengths.is_empty() {
+            return Err(AppError::Interrupted);
+        }
+    }
+    if aggregate.run_lengths.is_empty() {
+        return Err(AppError::Config(
+            "no run of the simulation finished, so there is nothing to write".to_string(),
+        ));
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    make_room_for_output(path, args)?;
+    let mut writer = BufWriter::new(File::create(path)?);
+    if is_csv(path) {
+        writeln!(writer, "rolls")?;
+        for length in &aggregate.run_lengths {
+            writeln!(writer, "{}", length)?;
+        }
+    } else {
+        write_lengths(&mut writer, args.num_prizes(), &aggregate.run_lengths)?;
+    }
+    writer.flush()?;
+    println!(
+        "Wrote {} runs to {}",
+        aggregate.run_lengths.len(),
+        path.display()
+    );
+
+    Ok(())
+}
+
+/// Chart the runs in the file at `path` written by `simulate`
+fn plot_runs_file(args: &Args, path: &Path) -> Result<(), AppError> {
+    let lengths = read_runs_file(args, path)?;
+    let hist_data = lengths_histogram(&lengths, path)?;
+    write_histogram(args, &hist_data, lengths.len(), &args.histogram_options())
+}
+
+/// Print the stats of the runs in the file at `path` written by `simulate`
+fn print_runs_file_stats(args: &Args, path: &Path) -> Result<(), AppError> {
+    let lengths = read_runs_file(args, path)?;
+    print_lengths_summary(args, &lengths_histogram(&lengths, path)?);
+    Ok(())
+}
+
+/// Whether `path` is written and read as a CSV rather than in the binary lengths format
+fn is_csv(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "csv")
+}
+
+/// Read the number of rolls of every run from a file written by `simulate`, as a CSV or in the
+/// binary lengths format depending on its extension
+fn read_runs_file(args: &Args, path: &Path) -> Result<Vec<u32>, AppError> {
+    if !is_csv(path) {
+        return read_lengths_file(args, path);
+    }
+
+    let contents = fs::read_to_string(path)
+        .map_err(|err| AppError::Config(format!("couldn't load '{}': {}", path.display(), err)))?;
+    let mut lines = contents.lines().enumerate().peekable();
+    // The header is optional, so that a column of numbers from elsewhere can be read too
+    lines.next_if(|(_, line)| line.trim() == "rolls");
+    lines
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(line_idx, line)| {
+            line.trim().parse().map_err(|_| {
+                AppError::Config(format!(
+                    "line {} of '{}': '{}' is not a number of rolls",
+                    line_idx + 1,
+                    path.display(),
+                    line.trim()
+                ))
+            })
+        })
+        .collect()
+}
+
+/// Read the number of rolls of every run from the binary lengths file at `path`
+fn read_lengths_file(args: &Args, path: &Path) -> Result<Vec<u32>, AppError> {
+    File::open(path)
+        .map_err(ReadLengthsError::from)
+        .and_then(|file| read_lengths(&mut BufReader::new(file), args.num_prizes()))
+        .map_err(|err| AppError::Config(format!("couldn't load '{}': {}", path.display(), err)))
+}
+
+/// Count how many of the runs read from `path` took each number of rolls, which is an error if
+/// there aren't any
+fn lengths_histogram(lengths: &[u32], path: &Path) -> Result<HashMap<usize, usize>, AppError> {
+    if lengths.is_empty() {
+        return Err(AppError::Config(format!(
+            "'{}' doesn't have any runs",
+            path.display()
+        )));
+    }
+
+    let mut hist_data = HashMap::new();
+    for &length in lengths {
+        *hist_data.entry(length as usize).or_insert(0) += 1;
+    }
+    Ok(hist_data)
+}
+
+/// Print the summary statistics of runs read from a file, and what they cost with a
+/// --cost-per-roll
+fn print_lengths_summary(args: &Args, hist_data: &HashMap<usize, usize>) {
+    let summary = analyze_histogram(hist_data, &args.percentiles);
+    if args.pretty {
+        print_summary_tables(&summary, args.precision, None);
+    } else {
+        print_summary(
+            &summary,
+            args.precision,
+            None,
+            color_enabled(args, std::io::stdout().is_terminal()),
+        );
+    }
+    if let Some(cost) = args.cost_model() {
+        print_cost_summary(&summary, &cost, args);
+    }
+}
+
+/// Draw the histogram of `runs` runs to the file the --output-template names
+fn write_histogram(
+    args: &Args,
+    hist_data: &HashMap<usize, usize>,
+    runs: usize,
+    options: &HistogramOptions,
+) -> Result<(), AppError> {
+    let path = prepare_output(args, runs, "")?;
+    render_png(&path, args.chart_layout(), args.transparent, |root| {
+        draw_histogram_into(root, hist_data, options)
+    })?;
+
+    Ok(())
+}
+
+/// The path the --output-template names for `runs` runs, with `suffix` added before the
+/// extension for charts that go next to the histogram. Missing directories are created, and an
+/// existing file is dealt with as --no-clobber or --backup say
+fn prepare_output(args: &Args, runs: usize, suffix: &str) -> Result<PathBuf, AppError> {
+    let template = insert_before_extension(&args.output_template, suffix);
+    let path =
+        PathBuf::from(expand_output_template(&template, args, runs).map_err(AppError::Config)?);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    make_room_for_output(&path, args)?;
+    Ok(path)
+}
+
+/// `path` with `suffix` added to the end of the file name, before its extension if it has one
+fn insert_before_extension(path: &str, suffix: &str) -> String {
+    let extension_start = path
+        .rfind('.')
+        .filter(|&dot| !path[dot..].contains('/'))
+        .unwrap_or(path.len());
+    let mut path = path.to_string();
+    path.insert_str(extension_start, suffix);
+    path
+}
+
+/// Replay the runs recorded at `path` and print every step of them
+fn replay(args: &Args, path: &Path) -> Result<(), AppError> {
+    let config = args.sim_config();
+    for (run_idx, rolls) in read_roll_sequences(path, config.prizes)?.iter().enumerate() {
+        println!("Run {}:", run_idx + 1);
+        let mut earned_prizes = vec![false; config.prizes];
+        let finished = replay_rolls(&config, rolls, |roll_idx, roll_result, was_new| {
+            let prize_idx = usize::from(roll_result);
+            earned_prizes[prize_idx] = true;
+            let mask: String = earned_prizes
+                .iter()
+                .map(|&earned| if earned { '1' } else { '0' })
+                .collect();
+            println!(
+                "  Roll {}: prize index {} ({}), earned {}",
+                roll_idx + 1,
+                prize_idx,
+                if was_new { "new" } else { "duplicate" },
+                mask
+            );
+        });
+        match finished {
+            Some(rolls_needed) if rolls_needed == rolls.len() => {
+                println!("  Finished after {} rolls", rolls_needed)
+            }
+            Some(rolls_needed) => println!(
+                "  Finished after {} rolls, and the {} rolls after that weren't needed",
+                rolls_needed,
+                rolls.len() - rolls_needed
+            ),
+            None => println!(
+                "  The {} rolls ran out before the run was over",
+                rolls.len()
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+/// Write each run's rolls as a line of space-separated prize indices
+fn write_roll_sequences(path: &Path, roll_sequences: &[Vec<RollResult>]) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    for rolls in roll_sequences {
+        write_roll_sequence(&mut writer, rolls)?;
+    }
+
+    writer.flush()
+}
+
+/// Write `run_records` to a fresh `runs` table of the SQLite database at `path`, numbering the
+/// runs from 1. Everything goes in one transaction, which is much faster than a commit per row
+#[cfg(feature = "sqlite")]
+fn write_sqlite(path: &Path, run_records: &[RunRecord]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut connection = rusqlite::Connection::open(path)?;
+    let transaction = connection.transaction()?;
+    transaction.execute_batch(
+        "DROP TABLE IF EXISTS runs;
+        CREATE TABLE runs (
+            run_id INTEGER PRIMARY KEY,
+            rolls INTEGER NOT NULL,
+            duplicates INTEGER NOT NULL,
+            pity_used INTEGER NOT NULL
+        );",
+    )?;
+    {
+        let mut insert = transaction.prepare(
+            "INSERT INTO runs (run_id, rolls, duplicates, pity_used) VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        for (run_idx, record) in run_records.iter().enumerate() {
+            // SQLite integers are signed 64 bits, and no run gets anywhere near that many rolls
+            insert.execute((
+                run_idx as i64 + 1,
+                record.rolls as i64,
+                record.duplicates as i64,
+                record.pity_used,
+            ))?;
+        }
+    }
+    transaction.commit()?;
+
+    Ok(())
+}
+
+/// Write the summary statistics as `stat,value` rows, with a `p{N}` row for every percentile
+fn write_summary_csv(path: &Path, summary: &StatsSummary) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "stat,value")?;
+    writeln!(writer, "runs,{}", summary.runs)?;
+    writeln!(writer, "mean,{}", summary.mean)?;
+    writeln!(writer, "std_dev,{}", summary.std_dev)?;
+    writeln!(writer, "min,{}", summary.min)?;
+    writeln!(writer, "max,{}", summary.max)?;
+    writeln!(writer, "median,{}", summary.median)?;
+    writeln!(writer, "mode,{}", summary.mode)?;
+    for percentile in &summary.percentiles {
+        writeln!(writer, "p{},{}", percentile.percentile, percentile.rolls)?;
+    }
+    writer.flush()
+}
+
+/// Header of the --append-csv file
+const APPEND_CSV_HEADER: &str = "timestamp,pid,rolls,count";
+
+/// Append the histogram to the CSV at `path`, writing the header first if the file is new
+fn append_histogram_csv(path: &Path, summary: &StatsSummary) -> Result<(), AppError> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .read(true)
+        .open(path)?;
+
+    if file.metadata()?.len() == 0 {
+        writeln!(file, "{}", APPEND_CSV_HEADER)?;
+    } else {
+        let mut header = String::new();
+        BufReader::new(&file).read_line(&mut header)?;
+        if header.trim_end() != APPEND_CSV_HEADER {
+            return Err(AppError::Config(format!(
+                "{} doesn't start with the header {}, so it wasn't written by --append-csv",
+                path.display(),
+                APPEND_CSV_HEADER
+            )));
+        }
+    }
+
+    // Build all the rows first and append them with a single write, so that other processes
+    // appending at the same time are unlikely to interleave with them
+    let timestamp = Local::now().to_rfc3339();
+    let pid = std::process::id();
+    let mut rows = String::new();
+    for (rolls, count) in &summary.histogram {
+        rows.push_str(&format!("{},{},{},{}\n", timestamp, pid, rolls, count));
+    }
+    file.write_all(rows.as_bytes())?;
+
+    Ok(())
+}
+
+/// Write the summary statistics in the Prometheus text exposition format. The percentiles make
+/// up the quantiles of a summary of the rolls of the finished runs
+fn write_metrics(
+    path: &Path,
+    summary: &StatsSummary,
+    completed: usize,
+    abandoned: usize,
+) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(
+        writer,
+        "# HELP uma_bingo_runs Number of runs simulated, including the abandoned ones"
+    )?;
+    writeln!(writer, "# TYPE uma_bingo_runs gauge")?;
+    writeln!(writer, "uma_bingo_runs {}", completed)?;
+    writeln!(
+        writer,
+        "# HELP uma_bingo_abandoned_ratio Fraction of the runs that gave up before finishing"
+    )?;
+    writeln!(writer, "# TYPE uma_bingo_abandoned_ratio gauge")?;
+    writeln!(
+        writer,
+        "uma_bingo_abandoned_ratio {}",
+        abandoned as f64 / completed as f64
+    )?;
+    writeln!(
+        writer,
+        "# HELP uma_bingo_rolls_mean Average number of rolls to earn every prize"
+    )?;
+    writeln!(writer, "# TYPE uma_bingo_rolls_mean gauge")?;
+    writeln!(writer, "uma_bingo_rolls_mean {}", summary.mean)?;
+    writeln!(
+        writer,
+        "# HELP uma_bingo_rolls Number of rolls the finished runs took to earn every prize"
+    )?;
+    writeln!(writer, "# TYPE uma_bingo_rolls summary")?;
+    for percentile in &summary.percentiles {
+        writeln!(
+            writer,
+            "uma_bingo_rolls{{quantile=\"{}\"}} {}",
+            percentile.percentile / 100.,
+            percentile.rolls
+        )?;
+    }
+    let total_rolls: usize = summary
+        .histogram
+        .iter()
+        .map(|(rolls, count)| rolls * count)
+        .sum();
+    writeln!(writer, "uma_bingo_rolls_sum {}", total_rolls)?;
+    writeln!(writer, "uma_bingo_rolls_count {}", summary.runs)?;
+
+    writer.flush()
+}
+
+/// [`simulate`] with a generator of the --rng kind, seeded with `seed` if there is one
+fn simulate_seeded(
+    args: &Args,
+    seed: Option<Seed>,
+    roll_sink: Option<&mut BufWriter<File>>,
+    interrupted: &AtomicBool,
+) -> std::io::Result<Aggregate> {
+    match (args.rng, seed) {
+        (RngKind::Small, Some(seed)) => {
+            simulate(&mut seed.rng::<SmallRng>(), args, roll_sink, interrupted)
+        }
+        (RngKind::Small, None) => {
+            simulate(&mut SmallRng::from_os_rng(), args, roll_sink, interrupted)
+        }
+        (RngKind::Std, Some(seed)) => {
+            simulate(&mut seed.rng::<StdRng>(), args, roll_sink, interrupted)
+        }
+        (RngKind::Std, None) => simulate(&mut StdRng::from_os_rng(), args, roll_sink, interrupted),
+        (RngKind::Thread, _) => simulate(&mut rand::rng(), args, roll_sink, interrupted),
+    }
+}
+
+/// Share the runs out over --threads threads, each simulating its share with its own generator,
+/// and merge what they aggregated. With a --duration, every thread runs for the whole duration
+fn simulate_in_parallel(
+    args: &Args,
+    seed: Option<Seed>,
+    interrupted: &AtomicBool,
+) -> std::io::Result<Aggregate> {
+    let simulator = Simulator::from(args.sim_config());
+    let options = args.simulate_options();
+    let threads = args.threads.get();
+    let thread_seed = |thread_idx: usize| seed.map(|seed| seed.offset((thread_idx as u64) << 32));
+    match args.rng {
+        RngKind::Small => simulator.simulate_in_parallel(
+            &options,
+            threads,
+            |thread_idx| thread_seed(thread_idx).map_or_else(SmallRng::from_os_rng, Seed::rng),
+            interrupted,
+        ),
+        RngKind::Std => simulator.simulate_in_parallel(
+            &options,
+            threads,
+            |thread_idx| thread_seed(thread_idx).map_or_else(StdRng::from_os_rng, Seed::rng),
+            interrupted,
+        ),
+        RngKind::Thread => {
+            simulator.simulate_in_parallel(&options, threads, |_| rand::rng(), interrupted)
+        }
+    }
+}
+
+/// Run simulations until the requested runs or duration are done, stopping early if `interrupted`
+/// gets set
+///
+/// With a `roll_sink`, the rolls for --dump-rolls are written to it as the runs finish instead of
+/// being kept in the aggregate.
+fn simulate<R: Rng>(
+    rng: &mut R,
+    args: &Args,
+    roll_sink: Option<&mut BufWriter<File>>,
+    interrupted: &AtomicBool,
+) -> std::io::Result<Aggregate> {
+    Simulator::from(args.sim_config()).simulate(
+        rng,
+        &args.simulate_options(),
+        roll_sink.map(|roll_sink| roll_sink as &mut dyn Write),
+        interrupted,
+    )
+}
+
+/// Print how many days of `rolls_per_day` rolls the runs in `hist_data` took to finish
+fn print_days_summary(hist_data: &HashMap<usize, usize>, rolls_per_day: usize, args: &Args) {
+    let days = analyze_histogram(&days_histogram(hist_data, rolls_per_day), &args.percentiles);
+
+    println!(
+        "Average number of days to earn all prizes with {} rolls a day: {:.*}",
+        rolls_per_day, args.precision, days.mean
+    );
+    println!("Median: {} days, max: {} days", days.median, days.max);
+    for percentile in &days.percentiles {
+        println!(
+            "{}th percentile: {} days",
+            percentile.percentile, percentile.rolls
+        );
+    }
+    for (days_taken, count) in &days.histogram {
+        println!(
+            "{} days: {} ({:.*}%)",
+            days_taken,
+            count,
+            args.precision,
+            (*count as f64 / days.runs as f64) * 100.
+        );
+    }
+}
+
+/// Turn a histogram of rolls into one of the days of `rolls_per_day` rolls they take, where a
+/// partly used day counts as a whole one
+fn days_histogram(
+    hist_data: &HashMap<usize, usize>,
+    rolls_per_day: usize,
+) -> HashMap<usize, usize> {
+    let mut days_data = HashMap::new();
+    for (&rolls, &count) in hist_data {
+        *days_data.entry(rolls.div_ceil(rolls_per_day)).or_insert(0) += count;
+    }
+    days_data
+}
+
+/// Print the summary statistics and the histogram as bordered tables, for --pretty
+fn print_summary_tables(
+    summary: &StatsSummary,
+    precision: usize,
+    theoretical_std_dev: Option<f64>,
+) {
+    let (stats_table, histogram_table) = summary_tables(summary, precision, theoretical_std_dev);
+    println!("{}", stats_table);
+    println!("{}", histogram_table);
+}
+
+/// The summary statistics and the histogram tables printed by [`print_summary_tables`]
+fn summary_tables(
+    summary: &StatsSummary,
+    precision: usize,
+    theoretical_std_dev: Option<f64>,
+) -> (Table, Table) {
+    let mut stats_table = new_table(["Statistic", "Value"]);
+    stats_table.add_row(["Runs".to_string(), summary.runs.to_string()]);
+    stats_table.add_row([
+        "Mean".to_string(),
+        format!("{:.*}", precision, summary.mean),
+    ]);
+    stats_table.add_row([
+        "Standard deviation".to_string(),
+        format!("{:.*}", precision, summary.std_dev),
+    ]);
+    if let Some(theoretical_std_dev) = theoretical_std_dev {
+        stats_table.add_row([
+            "Theoretical standard deviation without pity".to_string(),
+            format!("{:.*}", precision, theoretical_std_dev),
+        ]);
+    }
+    stats_table.add_row(["Min".to_string(), summary.min.to_string()]);
+    stats_table.add_row(["Max".to_string(), summary.max.to_string()]);
+    stats_table.add_row(["Median".to_string(), summary.median.to_string()]);
+    stats_table.add_row(["Mode".to_string(), summary.mode.to_string()]);
+    for percentile in &summary.percentiles {
+        stats_table.add_row([
+            format!("{}th percentile", percentile.percentile),
+            percentile.rolls.to_string(),
+        ]);
+    }
+    align_right(&mut stats_table, &[1]);
+
+    let mut histogram_table = new_table(["Rolls", "Runs", "Share"]);
+    for (rolls, count) in &summary.histogram {
+        histogram_table.add_row([
+            rolls.to_string(),
+            count.to_string(),
+            format!(
+                "{:.*}%",
+                precision,
+                (*count as f64 / summary.runs as f64) * 100.
+            ),
+        ]);
+    }
+    align_right(&mut histogram_table, &[0, 1, 2]);
+    (stats_table, histogram_table)
+}
+
+/// Create a --pretty table with a row separator under the header only
+fn new_table<const N: usize>(header: [&str; N]) -> Table {
+    let mut table = Table::new();
+    table.load_style(ASCII_FULL_CONDENSED).set_header(header);
+    table
+}
+
+/// Print the average number of rolls from each number of different prizes to the next, next to
+/// the no-pity coupon collector average when every prize is equally likely
+fn print_gaps(gap_totals: &[usize], gap_counts: &[usize], args: &Args) {
+    println!("Average rolls from each number of prizes to the next:");
+    let config = args.sim_config();
+    let baseline = |transition: usize| {
+        let earned = transition + 1;
+        config.prizes as f64 / (config.prizes - earned) as f64
+    };
+    let gaps = gap_totals
+        .iter()
+        .zip(gap_counts)
+        .enumerate()
+        .filter(|(_, (_, &count))| count > 0)
+        .map(|(transition, (&total, &count))| (transition, total as f64 / count as f64));
+    // The baseline assumes equally likely prizes, which an --event may change
+    let has_baseline = config.weights.is_none();
+    if args.pretty {
+        let mut table = if has_baseline {
+            new_table(["Prizes", "Rolls", "No-pity baseline"])
+        } else {
+            new_table(["Prizes", "Rolls"])
+        };
+        for (transition, mean_gap) in gaps {
+            let mut row = vec![
+                format!("{} -> {}", transition + 1, transition + 2),
+                format!("{:.*}", args.precision, mean_gap),
+            ];
+            if has_baseline {
+                row.push(format!("{:.*}", args.precision, baseline(transition)));
+            }
+            table.add_row(row);
+        }
+        align_right(&mut table, &[1, 2]);
+        println!("{}", table);
+    } else {
+        for (transition, mean_gap) in gaps {
+            print!(
+                "{} -> {}: {:.*}",
+                transition + 1,
+                transition + 2,
+                args.precision,
+                mean_gap
+            );
+            if has_baseline {
+                print!(
+                    " (no-pity baseline: {:.*})",
+                    args.precision,
+                    baseline(transition)
+                );
+            }
+            println!();
+        }
+    }
+}
+
+/// Print what the runs cost: the average, the extremes and the requested percentiles, all with
+/// --precision decimals
+fn print_cost_summary(summary: &StatsSummary, cost: &CostModel, args: &Args) {
+    let format_cost = |cost: f64| format!("{:.*}", args.precision, cost);
+    let mean = format_cost(cost.mean_cost(summary));
+    if args.pretty {
+        let mut table = new_table(["Currency spent", "Value"]);
+        table.add_row(["Mean".to_string(), mean]);
+        table.add_row(["Min".to_string(), format_cost(cost.cost(summary.min))]);
+        table.add_row(["Median".to_string(), format_cost(cost.cost(summary.median))]);
+        table.add_row(["Max".to_string(), format_cost(cost.cost(summary.max))]);
+        for percentile in &summary.percentiles {
+            table.add_row([
+                format!("{}th percentile", percentile.percentile),
+                format_cost(cost.cost(percentile.rolls)),
+            ]);
+        }
+        align_right(&mut table, &[1]);
+        println!("{}", table);
+    } else {
+        println!("Average currency spent per run: {}", mean);
+        println!(
+            "Currency spent, min: {}, median: {}, max: {}",
+            format_cost(cost.cost(summary.min)),
+            format_cost(cost.cost(summary.median)),
+            format_cost(cost.cost(summary.max))
+        );
+        for percentile in &summary.percentiles {
+            println!(
+                "{}th percentile of currency spent: {}",
+                percentile.percentile,
+                format_cost(cost.cost(percentile.rolls))
+            );
+        }
+    }
+}
+
+/// Print the mean, median and 95th percentile of each scenario side by side, one column per
+/// scenario. Without --pretty, the columns are only separated by spaces
+fn print_comparison_table(scenarios: &[(String, &StatsSummary)], precision: usize, pretty: bool) {
+    println!("{}", comparison_table(scenarios, precision, pretty));
+}
+
+/// The table printed by [`print_comparison_table`]
+fn comparison_table(
+    scenarios: &[(String, &StatsSummary)],
+    precision: usize,
+    pretty: bool,
+) -> Table {
+    let mut table = Table::new();
+    if pretty {
+        table.load_style(ASCII_FULL_CONDENSED);
+    } else {
+        table.load_style(NOTHING);
+    }
+    table.set_header(
+        std::iter::once(String::new())
+            .chain(scenarios.iter().map(|(name, _)| name.clone()))
+            .collect::<Vec<_>>(),
+    );
+    table.add_row(
+        std::iter::once("Mean".to_string())
+            .chain(
+                scenarios
+                    .iter()
+                    .map(|(_, summary)| format!("{:.*}", precision, summary.mean)),
+            )
+            .collect::<Vec<_>>(),
+    );
+    // How far each mean is from the first scenario's, which the others are usually variations of
+    let first_mean = scenarios.first().map_or(0., |(_, summary)| summary.mean);
+    table.add_row(
+        std::iter::once("Mean vs first".to_string())
+            .chain(
+                scenarios
+                    .iter()
+                    .map(|(_, summary)| format!("{:+.*}", precision, summary.mean - first_mean)),
+            )
+            .collect::<Vec<_>>(),
+    );
+    table.add_row(
+        std::iter::once("Median".to_string())
+            .chain(
+                scenarios
+                    .iter()
+                    .map(|(_, summary)| summary.median.to_string()),
+            )
+            .collect::<Vec<_>>(),
+    );
+    table.add_row(
+        std::iter::once("95th percentile".to_string())
+            .chain(
+                scenarios
+                    .iter()
+                    .map(|(_, summary)| summary.percentile(95.).to_string()),
+            )
+            .collect::<Vec<_>>(),
+    );
+    align_right(&mut table, &(1..=scenarios.len()).collect::<Vec<_>>());
+    table
+}
+
+/// Right-align the numeric columns of a table
+fn align_right(table: &mut Table, columns: &[usize]) {
+    for &column_idx in columns {
+        if let Some(column) = table.column_mut(column_idx) {
+            column.set_cell_alignment(CellAlignment::Right);
+        }
+    }
+}
+
+/// Describe the rules of the simulation in plain words and compare the simulated average with
+/// the theoretical one when it's known
+fn print_explanation(args: &Args, summary: &StatsSummary) {
+    let config = args.sim_config();
+    let required = config.required_count();
+
+    match &config.weights {
+        None => println!(
+            "Each roll grants one of {} prizes, all equally likely.",
+            config.prizes
+        ),
+        Some(weights) => {
+            let rarest = weights.rarest();
+            println!(
+                "Each roll grants one of {} prizes, with the chances given by --weights or the --event. The rarest is prize {} at {:.*}% a roll.",
+                config.prizes,
+                rarest + 1,
+                args.precision,
+                weights.probabilities()[rarest] * 100.
+            );
+        }
+    }
+    if let Some(deck_size) = config.deck_size {
+        println!(
+            "The rolls are drawn from a shuffled deck of {} tokens without putting them back, so a prize gets more likely the longer it hasn't come up.",
+            deck_size
+        );
+    }
+    if config.streakiness > 0. {
+        println!(
+            "A roll repeats the prize of the roll before it {:.*}% of the time.",
+            args.precision,
+            config.streakiness * 100.
+        );
+    }
+    if let Some(until) = &config.until {
+        println!("A run is over once {}.", until);
+    } else if required == config.prizes {
+        println!("A run is over once every prize has been collected.");
+    } else {
+        println!(
+            "A run is over once every prize set has been collected, which takes {} of the {} prizes.",
+            required, config.prizes
+        );
+    }
+    match config.pity_mode {
+        PityMode::Hard => println!(
+            "After {} rolls, pity makes every roll grant the first prize that's still needed.",
+            config.pity_threshold
+        ),
+        PityMode::Pool => println!(
+            "After {} rolls, pity makes every roll grant a random prize out of the ones that are still needed.",
+            config.pity_threshold
+        ),
+        PityMode::None => {
+            println!("There is no pity, so a run only ends once luck brings the last prize.")
+        }
+    }
+    if let Some(blp_threshold) = config.blp_threshold {
+        println!(
+            "The rarest prize is also granted after {} rolls in a row without it.",
+            blp_threshold
+        );
+    }
+    if let Some(give_up) = config.give_up {
+        println!(
+            "Runs that haven't finished after {} rolls are given up on.",
+            give_up
+        );
+    }
+
+    match theoretical_mean(&config) {
+        Some(theoretical_mean) => println!(
+            "In theory that takes {:.*} rolls on average, and the {} simulated runs took {:.*}.",
+            args.precision, theoretical_mean, summary.runs, args.precision, summary.mean
+        ),
+        None => println!(
+            "There's no exact formula for these rules, but the {} simulated runs took {:.*} rolls on average.",
+            summary.runs, args.precision, summary.mean
+        ),
+    }
+}
+
+/// Print the prizes from rarest to most common, with ties in prize order, along with their chance
+/// per roll and the average number of rolls it takes to roll each one, ignoring pity
+fn print_rarity_report(args: &Args) {
+    let ranking = rarity_ranking(&args.sim_config());
+
+    let expected_rolls = |probability: f64| {
+        if probability > 0. {
+            format!("{:.*}", args.precision, 1. / probability)
+        } else {
+            "never".to_string()
+        }
+    };
+    println!("Prizes from rarest to most common:");
+    if args.pretty {
+        let mut table = new_table(["Prize", "Chance", "Rolls to get it"]);
+        for &(prize_idx, probability) in &ranking {
+            table.add_row([
+                (prize_idx + 1).to_string(),
+                format!("{:.*}%", args.precision, probability * 100.),
+                expected_rolls(probability),
+            ]);
+        }
+        align_right(&mut table, &[0, 1, 2]);
+        println!("{}", table);
+    } else {
+        for &(prize_idx, probability) in &ranking {
+            let rolls = if probability > 0. {
+                format!("{} rolls on average to get it", expected_rolls(probability))
+            } else {
+                "never rolled".to_string()
+            };
+            println!(
+                "Prize {}: {:.*}% a roll, {}",
+                prize_idx + 1,
+                args.precision,
+                probability * 100.,
+                rolls
+            );
+        }
+    }
+}
+
+/// Every prize index with its chance per roll, from rarest to most common with ties in prize order
+fn rarity_ranking(config: &SimConfig) -> Vec<(usize, f64)> {
+    let probabilities = match &config.weights {
+        Some(weights) => weights.probabilities(),
+        None => vec![1. / config.prizes as f64; config.prizes],
+    };
+    let mut ranking: Vec<_> = probabilities.iter().copied().enumerate().collect();
+    ranking.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+    ranking
+}
+
+/// The exact average number of rolls under `config`, if it's simple enough for the exact formula:
+/// equally likely, independent rolls and no giving up
+fn theoretical_mean(config: &SimConfig) -> Option<f64> {
+    if config.weights.is_some()
+        || config.blp_threshold.is_some()
+        || config.deck_size.is_some()
+        || config.streakiness > 0.
+        || config.give_up.is_some()
+        || config.until.is_some()
+        || config.copies.iter().any(|&copies| copies != 1)
+    {
+        return None;
+    }
+    let pity_threshold = (config.pity_mode != PityMode::None).then_some(config.pity_threshold);
+    Some(exact_mean_rolls(
+        config.prizes,
+        config.required_count(),
+        pity_threshold,
+    ))
+}
+
+/// Print how many rolls pity saves on average compared to the same rules without pity. This is
+/// exact when the rules allow it, and otherwise compares the simulated average with another
+/// simulation without pity
+fn print_pity_savings(args: &Args, summary: &StatsSummary, interrupted: &AtomicBool) {
+    let (with_pity, without_pity) = match pity_savings(args, summary, interrupted) {
+        Ok(means) => means,
+        Err(reason) => {
+            println!("Rolls saved by pity: unknown, since {}", reason);
+            return;
+        }
+    };
+    let saved = without_pity - with_pity;
+    println!(
+        "Rolls saved by pity on average: {:.*} ({:.*}% fewer than the {:.*} without pity)",
+        args.precision,
+        saved,
+        args.precision,
+        saved / without_pity * 100.,
+        args.precision,
+        without_pity
+    );
+}
+
+/// The average number of rolls with and without pity that [`print_pity_savings`] compares, or
+/// why the one without pity can't be known
+fn pity_savings(
+    args: &Args,
+    summary: &StatsSummary,
+    interrupted: &AtomicBool,
+) -> Result<(f64, f64), &'static str> {
+    let config = args.sim_config();
+    let no_pity_config = SimConfig {
+        pity_mode: PityMode::None,
+        ..config.clone()
+    };
+    if config.pity_mode == PityMode::None {
+        Ok((summary.mean, summary.mean))
+    } else if let (Some(with_pity), Some(without_pity)) =
+        (theoretical_mean(&config), theoretical_mean(&no_pity_config))
+    {
+        Ok((with_pity, without_pity))
+    } else if no_pity_config.validate().is_err() {
+        Err("the runs can't finish without pity")
+    } else {
+        let no_pity = Simulator::from(no_pity_config);
+        let without_pity = match (args.rng, args.effective_seed()) {
+            (RngKind::Small, Some(seed)) => {
+                no_pity.mean_rolls(args.runs, &mut seed.rng::<SmallRng>(), interrupted)
+            }
+            (RngKind::Small, None) => {
+                no_pity.mean_rolls(args.runs, &mut SmallRng::from_os_rng(), interrupted)
+            }
+            (RngKind::Std, Some(seed)) => {
+                no_pity.mean_rolls(args.runs, &mut seed.rng::<StdRng>(), interrupted)
+            }
+            (RngKind::Std, None) => {
+                no_pity.mean_rolls(args.runs, &mut StdRng::from_os_rng(), interrupted)
+            }
+            (RngKind::Thread, _) => no_pity.mean_rolls(args.runs, &mut rand::rng(), interrupted),
+        };
+        let without_pity = without_pity.ok_or("no run finished without pity")?;
+        Ok((summary.mean, without_pity))
+    }
+}
+
+/// How wide --term-chart draws when the width of the terminal can't be found out, e.g. when the
+/// output is piped
+const DEFAULT_TERM_WIDTH: usize = 80;
+
+/// Print the histogram as rows of the number of rolls, a bar and the number of runs. The longest
+/// bar fills what's left of `width` columns, and the bars are drawn in eighths of a column
+fn print_term_chart(summary: &StatsSummary, width: usize) {
+    for line in term_chart_lines(summary, width) {
+        println!("{}", line);
+    }
+}
+
+/// The lines printed by [`print_term_chart`]
+fn term_chart_lines(summary: &StatsSummary, width: usize) -> Vec<String> {
+    const EIGHTHS: [char; 8] = [' ', '▏', '▎', '▍', '▌', '▋', '▊', '▉'];
+
+    let max_count = summary.histogram.values().copied().max().unwrap_or(0);
+    let rolls_width = summary.max.to_string().len();
+    let count_width = max_count.to_string().len();
+    // Leave room for the separator and the spaces around the count
+    let bar_width = width.saturating_sub(rolls_width + count_width + 4).max(1);
+    summary
+        .histogram
+        .iter()
+        .map(|(rolls, &count)| {
+            let eighths = count * bar_width * 8 / max_count;
+            let mut bar = "█".repeat(eighths / 8);
+            if !eighths.is_multiple_of(8) {
+                bar.push(EIGHTHS[eighths % 8]);
+            }
+            format!(
+                "{:>rolls_width$} │{:<bar_width$} {:>count_width$}",
+                rolls, bar, count
+            )
+        })
+        .collect()
+}
+
+/// Print the share of the `finished` runs, the average and the percentiles of one part of them,
+/// e.g. the ones that got a prize from pity
+fn print_split_stats(
+    description: &str,
+    hist_data: &HashMap<usize, usize>,
+    finished: usize,
+    args: &Args,
+) {
+    let runs: usize = hist_data.values().sum();
+    if runs == 0 {
+        println!("{}: none", description);
+        return;
+    }
+    let summary = analyze_histogram(hist_data, &args.percentiles);
+    print!(
+        "{}: {} ({:.*}%), {:.*} rolls on average",
+        description,
+        runs,
+        args.precision,
+        runs as f64 / finished as f64 * 100.,
+        args.precision,
+        summary.mean
+    );
+    for percentile in &summary.percentiles {
+        print!(
+            ", {}th percentile {}",
+            percentile.percentile, percentile.rolls
+        );
+    }
+    println!();
+}
+
+/// Print the summary statistics followed by the histogram
+fn print_summary(
+    summary: &StatsSummary,
+    precision: usize,
+    theoretical_std_dev: Option<f64>,
+    color: bool,
+) {
+    println!(
+        "Average number of rolls to earn all prizes: {}",
+        paint(
+            format!("{:.*}", precision, summary.mean),
+            Highlight::Key,
+            color
+        )
+    );
+    println!("Standard deviation: {:.*}", precision, summary.std_dev);
+    if let Some(theoretical_std_dev) = theoretical_std_dev {
+        println!(
+            "Theoretical standard deviation without pity: {:.*} (any pity would invalidate it)",
+            precision, theoretical_std_dev
+        );
+    }
+    println!("Min: {}, max: {}", summary.min, summary.max);
+    println!("Median: {}, mode: {}", summary.median, summary.mode);
+    for percentile in &summary.percentiles {
+        println!(
+            "{}th percentile: {}",
+            percentile.percentile,
+            paint(percentile.rolls, Highlight::Percentile, color)
+        );
+    }
+
+    for (rolls, count) in &summary.histogram {
+        println!(
+            "{}: {} ({:.*}%)",
+            rolls,
+            count,
+            precision,
+            (*count as f64 / summary.runs as f64) * 100.
+        );
+    }
+}
+
+/// Parse the command line, reading the --config file first if there is one so that the command
+/// line can override it
+fn parse_args(cli_args: &[OsString]) -> Result<Args, clap::Error> {
+    let matches = Cli::command().try_get_matches_from(cli_args)?;
+    let args = args_from_cli(Cli::from_arg_matches(&matches)?);
+    let Some(config_path) = &args.config else {
+        return Ok(args);
+    };
+
+    // A subcommand has the options, so they come after its name
+    let (subcommand, args_matches) = match matches.subcommand() {
+        Some((name, subcommand_matches)) => (Some(name), subcommand_matches),
+        None => (None, &matches),
+    };
+    let on_command_line =
+        |id: &str| args_matches.value_source(id) == Some(clap::parser::ValueSource::CommandLine);
+    let config_args = config_file_args(
+        config_path,
+        &<Args as clap::Args>::augment_args(clap::Command::new("uma-bingo")),
+        on_command_line,
+    )
+    .map_err(|message| {
+        Cli::command().error(
+            clap::error::ErrorKind::ValueValidation,
+            format!("invalid config file {}: {}", config_path.display(), message),
+        )
+    })?;
+    let options_start = 1 + usize::from(subcommand.is_some());
+    Cli::try_parse_from(
+        cli_args
+            .iter()
+            .take(options_start)
+            .cloned()
+            .chain(config_args.into_iter().map(OsString::from))
+            .chain(cli_args.iter().skip(options_start).cloned()),
+    )
+    .map(args_from_cli)
+}
+
+/// The options of the parsed command line, with the subcommand's file of runs if there is one
+fn args_from_cli(cli: Cli) -> Args {
+    let (mut args, runs_file) = match cli.command {
+        None => (cli.args, None),
+        Some(Command::Simulate { out, args }) => (args, Some(RunsFile::Write(out))),
+        Some(Command::Plot { path, args }) => (args, Some(RunsFile::Plot(path))),
+        Some(Command::Stats { path, args }) => (args, Some(RunsFile::Stats(path))),
+    };
+    args.runs_file = runs_file;
+    args
+}
+
+/// Turn the keys of a --config TOML file into command line arguments, leaving out the options the
+/// command line already sets. Keys are option names without the leading dashes, e.g.
+/// `runs = 1000` or `pity-mode = "pool"`. `true` turns a flag on and lists are joined with commas
+fn config_file_args(
+    path: &Path,
+    command: &clap::Command,
+    on_command_line: impl Fn(&str) -> bool,
+) -> Result<Vec<String>, String> {
+    let contents = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let table: toml::Table = contents
+        .parse()
+        .map_err(|err: toml::de::Error| err.to_string())?;
+
+    let mut config_args = Vec::new();
+    for (key, value) in table {
+        let id = key.replace('-', "_");
+        if !command
+            .get_arguments()
+            .any(|arg| arg.get_id() == id.as_str())
+        {
+            return Err(format!("unknown option '{}'", key));
+        }
+        if on_command_line(&id) {
+            continue;
+        }
+        let flag = format!("--{}", key.replace('_', "-"));
+        let value = match value {
+            toml::Value::Boolean(true) => {
+                config_args.push(flag);
+                continue;
+            }
+            toml::Value::Boolean(false) => continue,
+            toml::Value::String(value) => value,
+            toml::Value::Integer(value) => value.to_string(),
+            toml::Value::Float(value) => value.to_string(),
+            toml::Value::Array(values) => values
+                .iter()
+                .map(|value| match value {
+                    toml::Value::String(value) => value.clone(),
+                    value => value.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(","),
+            _ => {
+                return Err(format!(
+                    "'{}' has to be a string, number, boolean or list",
+                    key
+                ))
+            }
+        };
+        config_args.push(flag);
+        config_args.push(value);
+    }
+
+    Ok(config_args)
+}
+
+/// How often the watcher checks for Ctrl-C while waiting for the config file to change
+#[cfg(feature = "watch")]
+const WATCH_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long the config file has to go without changing before it is reloaded, so that an editor
+/// writing it in several steps only triggers one run
+#[cfg(feature = "watch")]
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// Run the simulation, then run it again every time the --config file is saved until Ctrl-C is
+/// pressed
+#[cfg(feature = "watch")]
+fn watch(mut args: Args, cli_args: &[OsString], interrupted: &AtomicBool) -> Result<(), AppError> {
+    use notify::{RecursiveMode, Watcher};
+    use std::sync::mpsc::{self, RecvTimeoutError};
+
+    let config_path = args.config.clone().unwrap(); // okay to unwrap because --watch requires it
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher =
+        notify::recommended_watcher(sender).map_err(|err| AppError::Io(err.into()))?;
+    // Editors often save by replacing the file, which would end a watch on the file itself, so
+    // watch the directory it's in instead
+    let config_dir = match config_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    watcher
+        .watch(config_dir, RecursiveMode::NonRecursive)
+        .map_err(|err| AppError::Io(err.into()))?;
+
+    loop {
+        match run(&args, interrupted) {
+            Ok(()) => {}
+            Err(AppError::Interrupted) => return Ok(()),
+            Err(err) => eprintln!("Error: {}", err),
+        }
+
+        println!(
+            "Watching {} for changes, press Ctrl-C to stop",
+            config_path.display()
+        );
+        loop {
+            loop {
+                if interrupted.load(Ordering::Relaxed) {
+                    return Ok(());
+                }
+                match receiver.recv_timeout(WATCH_POLL_INTERVAL) {
+                    // Reading the config generates access events of its own, so skip those
+                    Ok(Ok(event))
+                        if !event.kind.is_access()
+                            && event
+                                .paths
+                                .iter()
+                                .any(|path| path.file_name() == config_path.file_name()) =>
+                    {
+                        break
+                    }
+                    Ok(_) | Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => return Ok(()),
+                }
+            }
+            while receiver.recv_timeout(WATCH_DEBOUNCE).is_ok() {}
+
+            match parse_args(cli_args) {
+                Ok(new_args) => {
+                    args = new_args;
+                    break;
+                }
+                // Keep watching so the mistake can be fixed with another save
+                Err(err) => eprint!("{}", err),
+            }
+        }
+    }
+}
+
+fn main() {
+    let cli_args: Vec<OsString> = std::env::args_os().collect();
+    let args = parse_args(&cli_args).unwrap_or_else(|err| err.exit());
+
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let result = {
+        let interrupted = interrupted.clone();
+        ctrlc::set_handler(move || interrupted.store(true, Ordering::SeqCst))
+    }
+    .map_err(|err| AppError::Io(err.into()))
+    .and_then(|()| {
+        #[cfg(feature = "watch")]
+        if args.watch {
+            return watch(args, &cli_args, &interrupted);
+        }
+        run(&args, &interrupted)
+    });
+
+    if let Err(err) = result {
+        // The interrupted message has already been printed along with how far the runs got
+        if !matches!(err, AppError::Interrupted) {
+            eprintln!("Error: {}", err);
+        }
+        std::process::exit(err.exit_code());
+    }
+}
 
-use plotters::prelude::*;
-use rand::{
-    distr::{Distribution, StandardUniform},
-    Rng,
-};
+fn run(args: &Args, interrupted: &AtomicBool) -> Result<(), AppError> {
+    if let Some(runs_file) = &args.runs_file {
+        check_options(args)?;
+        return match runs_file {
+            RunsFile::Write(path) => write_runs_file(args, path, interrupted),
+            RunsFile::Plot(path) => plot_runs_file(args, path),
+            RunsFile::Stats(path) => print_runs_file_stats(args, path),
+        };
+    }
+    if args.self_test {
+        return self_test();
+    }
+    if let Some(sweep) = &args.sweep {
+        return run_sweep(args, sweep, interrupted);
+    }
+    check_options(args)?;
 
-#[derive(Debug)]
-enum RollResult {
-    FirstPrize,
-    SecondPrize,
-    ThirdPrize,
-    FourthPrize,
-    FifthPrize,
-    SixthPrize,
-    SeventhPrize,
-    EighthPrize,
+    if let Some(seeds) = args.compare_seeds {
+        return chart_seed_comparison(args, seeds, interrupted);
+    }
+    if let Some(replay_path) = &args.replay {
+        return replay(args, replay_path);
+    }
+    if let Some(load_lengths_path) = &args.load_lengths {
+        return load_lengths(args, load_lengths_path);
+    }
+    if let Some(pity_modes) = &args.compare_pity {
+        return print_pity_comparison(args, pity_modes, interrupted);
+    }
+    if let Some(pulls) = args.session_size {
+        return print_session_stats(args, pulls, interrupted);
+    }
+    if let Some(progress) = &args.remaining {
+        return remaining_rolls(args, progress);
+    }
+    if let Some(target_mean) = args.solve_pity_for_mean {
+        return print_solved_pity(args, target_mean, interrupted);
+    }
+
+    report(args, interrupted).map(|_| ())
 }
 
-impl TryFrom<usize> for RollResult {
-    type Error = &'static str;
+/// Run everything once for every value of the --sweep and compare the results in a table
+fn run_sweep(args: &Args, sweep: &Sweep, interrupted: &AtomicBool) -> Result<(), AppError> {
+    // Check every value up front rather than finding a bad one halfway through
+    let swept_args: Vec<_> = sweep
+        .values
+        .iter()
+        .map(|value| sweep.apply(args, value))
+        .collect();
+    for args in &swept_args {
+        check_options(args)?;
+    }
+
+    let mut summaries = Vec::new();
+    for (value, args) in sweep.values.iter().zip(&swept_args) {
+        println!("{} = {}:", sweep.parameter, value);
+        summaries.push((
+            format!("{}={}", sweep.parameter, value),
+            report(args, interrupted)?,
+        ));
+        println!();
+    }
+    let scenarios: Vec<_> = summaries
+        .iter()
+        .map(|(name, summary)| (name.clone(), summary))
+        .collect();
+    print_comparison_table(&scenarios, args.precision, args.pretty);
 
-    fn try_from(value: usize) -> Result<Self, Self::Error> {
-        match value {
-            0 => Ok(RollResult::FirstPrize),
-            1 => Ok(RollResult::SecondPrize),
-            2 => Ok(RollResult::ThirdPrize),
-            3 => Ok(RollResult::FourthPrize),
-            4 => Ok(RollResult::FifthPrize),
-            5 => Ok(RollResult::SixthPrize),
-            6 => Ok(RollResult::SeventhPrize),
-            7 => Ok(RollResult::EighthPrize),
-            _ => Err("Can only convert 0..=7 to RollResult"),
-        }
+    if let Some(overlay_path) = &args.overlay {
+        let histograms: Vec<_> = summaries
+            .iter()
+            .map(|(name, summary)| {
+                let hist_data = summary
+                    .histogram
+                    .iter()
+                    .map(|(&rolls, &count)| (rolls, count));
+                (name.clone(), hist_data.collect())
+            })
+            .collect();
+        render_png(
+            overlay_path,
+            args.chart_layout(),
+            args.transparent,
+            |root| {
+                draw_overlaid_histograms(
+                    root,
+                    &histograms,
+                    &format!(
+                        "{} simulations of uma-bingo for each {} value",
+                        args.runs, sweep.parameter
+                    ),
+                )
+            },
+        )?;
     }
+
+    Ok(())
 }
 
-impl From<&RollResult> for usize {
-    fn from(value: &RollResult) -> Self {
-        match value {
-            RollResult::FirstPrize => 0,
-            RollResult::SecondPrize => 1,
-            RollResult::ThirdPrize => 2,
-            RollResult::FourthPrize => 3,
-            RollResult::FifthPrize => 4,
-            RollResult::SixthPrize => 5,
-            RollResult::SeventhPrize => 6,
-            RollResult::EighthPrize => 7,
+/// Reject options that don't make sense together but that clap can't check on its own
+fn check_options(args: &Args) -> Result<(), AppError> {
+    let config = args.sim_config();
+    config.validate().map_err(AppError::Config)?;
+    if let Some(tail_cap) = args.tail_cap {
+        if tail_cap <= config.min_rolls() {
+            return Err(AppError::Config(format!(
+                "--tail-cap has to be more than {}, the fewest rolls a run can take",
+                config.min_rolls()
+            )));
+        }
+    }
+    if args.gof && args.pity_mode == PityMode::None {
+        return Err(AppError::Config(
+            "--gof compares against the exact distribution with pity, so it needs a pity mode other than none"
+                .to_string(),
+        ));
+    }
+    if args.gof && theoretical_mean(&config).is_none() {
+        return Err(AppError::Config(
+            "--gof needs equally likely prizes rolled independently, one copy each, until every set is done, so it can't be used with --weights, --deck-size, --streakiness, --blp-threshold, --give-up or --until"
+                .to_string(),
+        ));
+    }
+    if let Some(prize) = args.prob_last.filter(|&prize| prize > config.prizes) {
+        return Err(AppError::Config(format!(
+            "--prob-last {} is past the last prize, the prizes are numbered from 1 to {}",
+            prize, config.prizes
+        )));
+    }
+    if let Some(progress) = &args.remaining {
+        if progress.earned_prizes.len() != config.prizes {
+            return Err(AppError::Config(format!(
+                "the --remaining mask needs one character for each of the {} prizes, got {}",
+                config.prizes,
+                progress.earned_prizes.len()
+            )));
         }
     }
+    if args.effective_seed().is_some() && matches!(args.rng, RngKind::Thread) {
+        return Err(AppError::Config(
+            "--seed, --seed-hex and --seed-file need a seedable --rng (small or std)".to_string(),
+        ));
+    }
+
+    Ok(())
 }
 
-impl Distribution<RollResult> for StandardUniform {
-    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> RollResult {
-        rng.random_range(0..8).try_into().unwrap()
+/// Simulate the runs and print and write everything asked for about them, returning their summary
+/// statistics
+fn report(args: &Args, interrupted: &AtomicBool) -> Result<StatsSummary, AppError> {
+    let stream_rolls = streams_rolls(args);
+    if stream_rolls {
+        eprintln!(
+            "Keeping every roll for --dump-rolls could take more than --max-memory, so they are written out as the runs finish instead"
+        );
+    } else if args.dump_rolls.is_some()
+        && (args.duration.is_some() || args.runs * args.repeat > DUMP_ROLLS_WARNING_RUNS)
+    {
+        eprintln!(
+            "{}",
+            paint(
+                format!(
+                    "Warning: --dump-rolls keeps every roll in memory, which takes around {} bytes per run",
+                    ROLL_SEQUENCE_BYTES
+                ),
+                Highlight::Warning,
+                color_enabled(args, std::io::stderr().is_terminal()),
+            )
+        );
     }
-}
+    let mut roll_sink = match (&args.dump_rolls, stream_rolls) {
+        (Some(dump_rolls_path), true) => Some(BufWriter::new(File::create(dump_rolls_path)?)),
+        _ => None,
+    };
 
-/// Keep rolling for prizes until all prizes have been earned, then return the result
-fn run_sim() -> Vec<RollResult> {
-    let mut results = Vec::new();
-    let mut rng = rand::rng();
-    //                       first  second third  fourth fifth  sixth  seventh eighth
-    let mut earned_prizes = [false, false, false, false, false, false, false, false];
+    if args.rarity_report {
+        print_rarity_report(args);
+    }
+
+    // A --seed-file's seed isn't on the command line, so keep a record of it with the results
+    if let Some(seed @ Seed::Full(_)) = args.effective_seed() {
+        println!("Seed: {}", seed);
+    }
+
+    let start = Instant::now();
+    let mut aggregate = Aggregate::default();
+    let mut experiment_averages = Vec::new();
+    for experiment_idx in 0..args.repeat as u64 {
+        if interrupted.load(Ordering::Relaxed) {
+            break;
+        }
 
-    while earned_prizes.iter().filter(|&&earned| earned).count() < 8 {
-        let roll_result = if results.len() < 25 {
-            rng.random::<RollResult>()
+        // Every experiment gets a freshly seeded generator so they are independent of each other
+        let seed = args
+            .effective_seed()
+            .map(|seed| seed.offset(experiment_idx));
+        let experiment = if args.threads.get() == 1 {
+            simulate_seeded(args, seed, roll_sink.as_mut(), interrupted)?
         } else {
-            // It's not truly random, but after 25 rolls we get an unearned prize every time so it
-            // doesn't matter
-            earned_prizes
+            simulate_in_parallel(args, seed, interrupted)?
+        };
+        let finished = experiment.completed - experiment.abandoned;
+        if finished > 0 {
+            experiment_averages.push(experiment.total_rolls as f64 / finished as f64);
+        }
+        aggregate.merge(experiment);
+    }
+    let pair_standard_error = aggregate.pair_standard_error();
+    let Aggregate {
+        hist_data,
+        first_earned,
+        collection_order,
+        last_earned,
+        total_rng_draws,
+        completed,
+        abandoned,
+        roll_sequences,
+        run_lengths,
+        gap_totals,
+        gap_counts,
+        autocorrelation,
+        hist_with_pity,
+        hist_without_pity,
+        hist_with_event,
+        hist_without_event,
+        pairs,
+        #[cfg(feature = "sqlite")]
+        run_records,
+        ..
+    } = aggregate;
+    let simulation_time = start.elapsed();
+    let stats_start = Instant::now();
+
+    let was_interrupted = interrupted.load(Ordering::SeqCst);
+    if was_interrupted {
+        match args.duration {
+            Some(_) => println!("Interrupted after {} runs", completed),
+            None => println!(
+                "Interrupted after {} of {} runs",
+                completed,
+                args.runs * args.repeat
+            ),
+        }
+        if completed == 0 {
+            return Err(AppError::Interrupted);
+        }
+    }
+
+    if args.duration.is_some() {
+        println!(
+            "Completed {} runs in {:.1} seconds",
+            completed,
+            start.elapsed().as_secs_f64()
+        );
+    }
+
+    if args.give_up.is_some() {
+        println!(
+            "Gave up on {} of {} runs ({:.*}%)",
+            abandoned,
+            completed,
+            args.precision,
+            abandoned as f64 / completed as f64 * 100.
+        );
+        if abandoned == completed {
+            return Err(AppError::Config(
+                "every run gave up, so there are no finished runs to analyze".to_string(),
+            ));
+        }
+        println!("The stats below only cover the runs that finished");
+    }
+    // A --duration too short for a single run leaves nothing to analyze either
+    let summary = summarize_finished(&hist_data, &args.percentiles, "the simulation")?;
+    // The coupon collector variance only holds for plain rules with pity off
+    let config = args.sim_config();
+    let theoretical_std_dev = (config.pity_mode == PityMode::None
+        && theoretical_mean(&config).is_some()
+        && config.required_count() == config.prizes)
+        .then(|| theoretical_variance(config.prizes).sqrt());
+    if args.pretty {
+        print_summary_tables(&summary, args.precision, theoretical_std_dev);
+    } else {
+        print_summary(
+            &summary,
+            args.precision,
+            theoretical_std_dev,
+            color_enabled(args, std::io::stdout().is_terminal()),
+        );
+    }
+    if let Some(cost) = args.cost_model() {
+        print_cost_summary(&summary, &cost, args);
+    }
+
+    if args.term_chart {
+        let width = terminal_size::terminal_size()
+            .map_or(DEFAULT_TERM_WIDTH, |(terminal_size::Width(width), _)| {
+                usize::from(width)
+            });
+        print_term_chart(&summary, width);
+    }
+
+    if let Some(standard_error) = pair_standard_error.filter(|_| args.variance_reduction) {
+        println!(
+            "Standard error of the average from {} antithetic pairs: {:.*} (95% CI {:.*} to {:.*}), independent runs would give about {:.*}",
+            pairs,
+            args.precision,
+            standard_error,
+            args.precision,
+            summary.mean - 1.96 * standard_error,
+            args.precision,
+            summary.mean + 1.96 * standard_error,
+            args.precision,
+            summary.standard_error()
+        );
+    }
+
+    if args.repeat > 1 {
+        let mean = experiment_averages.iter().sum::<f64>() / experiment_averages.len() as f64;
+        let variance = experiment_averages
+            .iter()
+            .map(|average| (average - mean).powi(2))
+            .sum::<f64>()
+            / (experiment_averages.len() - 1).max(1) as f64;
+        println!(
+            "Average over {} experiments: {:.*} (std dev {:.*}, min {:.*}, max {:.*})",
+            experiment_averages.len(),
+            args.precision,
+            mean,
+            args.precision,
+            variance.sqrt(),
+            args.precision,
+            experiment_averages
                 .iter()
+                .copied()
+                .fold(f64::INFINITY, f64::min),
+            args.precision,
+            experiment_averages
+                .iter()
+                .copied()
+                .fold(f64::NEG_INFINITY, f64::max)
+        );
+    }
+
+    if args.extended_stats {
+        println!(
+            "Average RNG draws per run: {:.*}",
+            args.precision,
+            total_rng_draws as f64 / completed as f64
+        );
+        // Every finished run rolls each required prize as many times as it needs copies, so the
+        // rest were duplicates. An --until condition with an `or` can finish without some of the
+        // prizes it mentions, so there's no telling how many rolls were needed
+        if args.until.is_none() {
+            let config = args.sim_config();
+            print!(
+                "Average duplicate rolls per run: {:.*}",
+                args.precision,
+                summary.mean - config.min_rolls() as f64
+            );
+            if config.weights.is_none() && config.copies.iter().all(|&copies| copies == 1) {
+                print!(
+                    " (no-pity baseline: {:.*})",
+                    args.precision,
+                    expected_duplicates_without_pity(config.prizes, config.required_count())
+                );
+            }
+            println!();
+        }
+        println!(
+            "Skewness: {:.*}, excess kurtosis: {:.*}",
+            args.precision, summary.skewness, args.precision, summary.excess_kurtosis
+        );
+        // How much of the effort goes into a few of the prizes, usually the last one or two
+        let mean_gaps: Vec<f64> = gap_totals
+            .iter()
+            .zip(&gap_counts)
+            .filter(|(_, &count)| count > 0)
+            .map(|(&total, &count)| total as f64 / count as f64)
+            .collect();
+        println!(
+            "Gini coefficient of the average rolls between new prizes: {:.*}",
+            args.precision,
+            gini_coefficient(&mean_gaps)
+        );
+        print_pity_savings(args, &summary, interrupted);
+    }
+
+    if args.split_by_pity {
+        print_split_stats(
+            "Runs that finished without pity",
+            &hist_without_pity,
+            completed - abandoned,
+            args,
+        );
+        print_split_stats(
+            "Runs that got a prize from pity",
+            &hist_with_pity,
+            completed - abandoned,
+            args,
+        );
+    }
+
+    if let Some(early_event) = args.condition_on {
+        let (with_event, without_event) = early_event.descriptions();
+        print_split_stats(&with_event, &hist_with_event, completed - abandoned, args);
+        print_split_stats(
+            &without_event,
+            &hist_without_event,
+            completed - abandoned,
+            args,
+        );
+    }
+
+    if args.rng_diagnostics && autocorrelation.count() > 2 {
+        let lag1 = autocorrelation.lag1();
+        let bound = autocorrelation.significance_bound();
+        print!(
+            "Lag-1 autocorrelation of the rolls of consecutive runs: {:.*e}",
+            args.precision, lag1
+        );
+        if lag1.abs() > bound {
+            println!(
+                " (beyond the {:.*e} expected of independent runs, so the runs may be correlated)",
+                args.precision, bound
+            );
+        } else {
+            println!(
+                " (within the {:.*e} expected of independent runs)",
+                args.precision, bound
+            );
+        }
+    }
+
+    if args.explain {
+        print_explanation(args, &summary);
+    }
+
+    if let Some(json_path) = &args.json {
+        let writer = BufWriter::new(File::create(json_path)?);
+        serde_json::to_writer_pretty(writer, &summary)?;
+    }
+
+    if let Some(csv_path) = &args.csv {
+        write_summary_csv(csv_path, &summary)?;
+    }
+
+    if args.gof {
+        let config = args.sim_config();
+        let pmf = exact_pmf(
+            config.prizes,
+            config.required_count(),
+            config.pity_threshold,
+        );
+        let test = ks_test(&hist_data, &pmf, args.alpha);
+        println!(
+            "Exact expected number of rolls: {:.*}",
+            args.precision,
+            pmf.iter()
                 .enumerate()
-                .find_map(|(index, &earned)| if !earned { Some(index) } else { None })
-                .unwrap() // okay to unwrap because there must be at least one unearned prize
-                .try_into()
-                .unwrap() // okay to unwrap because the index must be in range
+                .map(|(rolls, prob)| rolls as f64 * prob)
+                .sum::<f64>()
+        );
+        println!(
+            "Kolmogorov-Smirnov test against the exact distribution: D = {:.*e}, critical value {:.*e} at alpha = {}: {}",
+            args.precision,
+            test.statistic,
+            args.precision,
+            test.critical_value,
+            args.alpha,
+            if test.passed() { "pass" } else { "fail" }
+        );
+    }
+
+    if let Some(rolls) = args.query_within {
+        let inclusive = !args.within_exclusive;
+        println!(
+            "P(rolls {} {}) = {:.*}%",
+            if inclusive { '≤' } else { '<' },
+            rolls,
+            args.precision,
+            summary.probability_within(rolls, inclusive) * 100.
+        );
+    }
+
+    if let Some(prize) = args.prob_last {
+        let finished: usize = last_earned.iter().sum();
+        println!(
+            "P(prize {} is the last new prize) = {:.*}% (every prize equally likely to be last: {:.*}%)",
+            prize,
+            args.precision,
+            last_earned[prize - 1] as f64 / finished as f64 * 100.,
+            args.precision,
+            100. / args.num_prizes() as f64
+        );
+    }
+
+    if let Some(rolls_per_day) = args.rolls_per_day {
+        print_days_summary(&hist_data, rolls_per_day.get(), args);
+    }
+
+    if args.gaps {
+        print_gaps(&gap_totals, &gap_counts, args);
+    }
+
+    if let Some(rolls) = args.per_prize_by {
+        let probabilities = earned_within(
+            &first_earned,
+            args.num_prizes(),
+            completed - abandoned,
+            rolls,
+        );
+        println!("Chance of having each prize within {} rolls:", rolls);
+        if args.pretty {
+            let mut table = new_table(["Prize", "Chance"]);
+            for (prize_idx, probability) in probabilities.iter().enumerate() {
+                table.add_row([
+                    (prize_idx + 1).to_string(),
+                    format!("{:.*}%", args.precision, probability * 100.),
+                ]);
+            }
+            align_right(&mut table, &[0, 1]);
+            println!("{}", table);
+        } else {
+            for (prize_idx, probability) in probabilities.iter().enumerate() {
+                println!(
+                    "Prize {}: {:.*}%",
+                    prize_idx + 1,
+                    args.precision,
+                    probability * 100.
+                );
+            }
+        }
+    }
+
+    let stats_time = stats_start.elapsed();
+    let output_start = Instant::now();
+
+    let histogram_options = args.histogram_options();
+    write_histogram(args, &hist_data, completed, &histogram_options)?;
+
+    if let Some(html_path) = &args.html {
+        write_html_report(
+            html_path,
+            &summary,
+            args.precision,
+            args.chart_layout(),
+            args.transparent,
+            &histogram_options,
+        )?;
+    }
+
+    if let Some(order_matrix_path) = &args.order_matrix {
+        write_order_matrix(order_matrix_path, &collection_order)?;
+    }
+
+    match (&args.dump_rolls, roll_sink) {
+        (_, Some(mut roll_sink)) => roll_sink.flush()?,
+        (Some(dump_rolls_path), None) => write_roll_sequences(dump_rolls_path, &roll_sequences)?,
+        (None, None) => {}
+    }
+
+    if let Some(append_csv_path) = &args.append_csv {
+        append_histogram_csv(append_csv_path, &summary)?;
+    }
+
+    if let Some(dump_lengths_path) = &args.dump_lengths {
+        let mut writer = BufWriter::new(File::create(dump_lengths_path)?);
+        write_lengths(&mut writer, args.num_prizes(), &run_lengths)?;
+        writer.flush()?;
+    }
+
+    #[cfg(feature = "sqlite")]
+    if let Some(sqlite_path) = &args.sqlite {
+        write_sqlite(sqlite_path, &run_records)?;
+    }
+
+    if let Some(metrics_path) = &args.metrics {
+        write_metrics(metrics_path, &summary, completed, abandoned)?;
+    }
+
+    if let Some(heatmap_path) = &args.heatmap {
+        render_png(
+            heatmap_path,
+            args.chart_layout(),
+            args.transparent,
+            |root| draw_heatmap(root, &first_earned, args.num_prizes(), completed),
+        )?;
+    }
+
+    if let Some(hazard_path) = &args.hazard {
+        render_png(hazard_path, args.chart_layout(), args.transparent, |root| {
+            draw_hazard_chart(root, &summary)
+        })?;
+    }
+
+    if let Some(cdf_path) = &args.cdf {
+        render_png(cdf_path, args.chart_layout(), args.transparent, |root| {
+            draw_cdf_chart(root, &summary, args.budget, args.cost_model().as_ref())
+        })?;
+    }
+
+    if args.profile {
+        let output_time = output_start.elapsed();
+        let total_time = simulation_time + stats_time + output_time;
+        println!("Time spent:");
+        for (phase, time) in [
+            ("simulation", simulation_time),
+            ("statistics", stats_time),
+            ("output", output_time),
+        ] {
+            println!(
+                "  {:<10} {:>9.3} s ({:.1}%)",
+                phase,
+                time.as_secs_f64(),
+                time.as_secs_f64() / total_time.as_secs_f64() * 100.
+            );
+        }
+    }
+
+    if let Some(summary_chart_path) = &args.summary_chart {
+        let simulator = Simulator::from(args.sim_config());
+        let scenarios = match (args.rng, args.effective_seed()) {
+            (RngKind::Small, Some(seed)) => simulator.simulate_prize_counts(
+                args.runs,
+                &mut seed.rng::<SmallRng>(),
+                interrupted,
+            )?,
+            (RngKind::Small, None) => simulator.simulate_prize_counts(
+                args.runs,
+                &mut SmallRng::from_os_rng(),
+                interrupted,
+            )?,
+            (RngKind::Std, Some(seed)) => simulator.simulate_prize_counts(
+                args.runs,
+                &mut seed.rng::<StdRng>(),
+                interrupted,
+            )?,
+            (RngKind::Std, None) => simulator.simulate_prize_counts(
+                args.runs,
+                &mut StdRng::from_os_rng(),
+                interrupted,
+            )?,
+            (RngKind::Thread, _) => {
+                simulator.simulate_prize_counts(args.runs, &mut rand::rng(), interrupted)?
+            }
         };
+        for (num_prizes, summary) in &scenarios {
+            let (low, high) = summary.mean_confidence_interval();
+            println!(
+                "{} prizes: {:.*} rolls on average (95% CI {:.*} to {:.*})",
+                num_prizes, args.precision, summary.mean, args.precision, low, args.precision, high
+            );
+        }
+        let named_scenarios: Vec<_> = scenarios
+            .iter()
+            .map(|(num_prizes, summary)| (format!("{} prizes", num_prizes), summary))
+            .collect();
+        print_comparison_table(&named_scenarios, args.precision, args.pretty);
+        if !scenarios.is_empty() {
+            render_png(
+                summary_chart_path,
+                args.chart_layout(),
+                args.transparent,
+                |root| draw_summary_chart(root, &scenarios, args.runs),
+            )?;
+        }
+    }
 
-        earned_prizes[usize::from(&roll_result)] = true;
-        results.push(roll_result);
+    if was_interrupted || interrupted.load(Ordering::Relaxed) {
+        return Err(AppError::Interrupted);
     }
 
-    results
+    Ok(summary)
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let mut results = Vec::new();
-    let runs = 1000000;
-    for _ in 0..runs {
-        results.push(run_sim());
+#[cfg(test)]
+mod tests {
+    use uma_bingo::analyze;
+
+    use super::*;
+
+    /// The seed [`test_args`] simulates with, and that the tests seed their own runs with
+    const TEST_SEED: u64 = 1;
+
+    /// A path in the temporary directory that no other test uses
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("uma-bingo-test-{}-{}", std::process::id(), name))
     }
-    println!(
-        "Average number of rolls to earn all prizes: {}",
-        results.iter().fold(0, |sum, sim_res| sum + sim_res.len()) as f32 / runs as f32
-    );
 
-    let mut hist_data: HashMap<usize, usize> = HashMap::new();
-    for sim_res in results {
-        hist_data
-            .entry(sim_res.len())
-            .and_modify(|count| *count += 1)
-            .or_insert(1);
+    /// The options of a small seeded simulation plus `extra`, charting to a file named after
+    /// `name` in the temporary directory
+    fn test_args(name: &str, extra: &[&str]) -> Args {
+        let output = temp_path(&format!("{}.png", name));
+        let cli_args: Vec<OsString> = ["uma-bingo", "--runs", "200", "--rng", "small"]
+            .iter()
+            .map(OsString::from)
+            .chain([
+                OsString::from("--seed"),
+                OsString::from(TEST_SEED.to_string()),
+            ])
+            .chain([OsString::from("-o"), output.into_os_string()])
+            .chain(extra.iter().map(OsString::from))
+            .collect();
+        parse_args(&cli_args).unwrap()
     }
 
-    for (idx, count) in hist_data.iter() {
-        println!(
-            "{}: {} ({}%)",
-            idx,
-            count,
-            (*count as f64 / runs as f64) * 100.
+    /// Run `report` on `args` and remove the chart it wrote
+    fn run_report(args: &Args) -> StatsSummary {
+        let summary = report(args, &AtomicBool::new(false)).unwrap();
+        let _ = fs::remove_file(&args.output_template);
+        summary
+    }
+
+    #[test]
+    fn transparent_png_has_see_through_corners() {
+        let path = temp_path("transparent.png");
+        let layout = ChartLayout {
+            size: (64, 48),
+            margin: 0,
+        };
+        render_png(&path, layout, true, |root| {
+            root.draw(&Rectangle::new([(16, 12), (48, 36)], RED.filled()))?;
+            Ok(())
+        })
+        .unwrap();
+
+        let image = image::open(&path).unwrap().to_rgba8();
+        fs::remove_file(&path).unwrap();
+        for (x, y) in [(0, 0), (63, 0), (0, 47), (63, 47)] {
+            assert_eq!(image.get_pixel(x, y)[3], 0, "corner ({}, {})", x, y);
+        }
+        assert_eq!(image.get_pixel(32, 24).0, [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn one_repeat_is_the_single_experiment() {
+        let args = test_args("one-repeat", &["--repeat", "1"]);
+        let summary = run_report(&args);
+
+        let single = simulate(
+            &mut SmallRng::seed_from_u64(TEST_SEED),
+            &args,
+            None,
+            &AtomicBool::new(false),
+        )
+        .unwrap();
+        assert_eq!(
+            summary.histogram,
+            analyze_histogram(&single.hist_data, &[]).histogram
+        );
+
+        let repeated = run_report(&test_args("three-repeats", &["--repeat", "3"]));
+        assert_eq!(repeated.runs, 3 * 200);
+    }
+
+    /// The cells of the row of `table` whose first cell is `name`
+    fn table_row(table: &Table, name: &str) -> Vec<String> {
+        table
+            .row_iter()
+            .map(|row| {
+                row.cell_iter()
+                    .map(|cell| cell.content())
+                    .collect::<Vec<_>>()
+            })
+            .find(|cells| cells[0] == name)
+            .unwrap_or_else(|| panic!("no {} row", name))
+    }
+
+    #[test]
+    fn summary_tables_round_to_the_precision() {
+        let summary = analyze(&[8, 9, 10, 12], &[]);
+
+        let (stats, histogram) = summary_tables(&summary, 0, Some(1.25));
+        assert_eq!(table_row(&stats, "Mean")[1], "10");
+        assert_eq!(
+            table_row(&stats, "Theoretical standard deviation without pity")[1],
+            "1"
+        );
+        assert_eq!(table_row(&histogram, "8")[2], "25%");
+
+        let (stats, histogram) = summary_tables(&summary, 3, Some(1.25));
+        assert_eq!(table_row(&stats, "Mean")[1], "9.750");
+        assert_eq!(
+            table_row(&stats, "Theoretical standard deviation without pity")[1],
+            "1.250"
         );
+        assert_eq!(table_row(&histogram, "8")[2], "25.000%");
+        // Whole roll counts aren't padded with decimals
+        assert_eq!(table_row(&stats, "Median")[1], "9");
     }
 
-    let max_count = *hist_data.values().max().unwrap();
-    let left_label_area_size = if max_count > 500 { 100 } else { 50 };
+    #[test]
+    fn output_template_fills_in_every_placeholder() {
+        let args = test_args(
+            "template",
+            &[
+                "--pity-mode",
+                "pool",
+                "--pity-threshold",
+                "30",
+                "--sets",
+                "0,1;2",
+            ],
+        );
+        assert_eq!(
+            expand_output_template(
+                "out/{prizes}-{pity_mode}-{pity}-{seed}-{sweep}-{runs}.png",
+                &args,
+                500
+            )
+            .unwrap(),
+            "out/3-pool-30-1-none-500.png"
+        );
+        assert_eq!(
+            expand_output_template("{date}.png", &args, 500).unwrap(),
+            format!("{}.png", Local::now().format("%Y-%m-%d"))
+        );
+        assert_eq!(
+            expand_output_template("plain.png", &args, 500).unwrap(),
+            "plain.png"
+        );
+
+        assert!(expand_output_template("{runs.png", &args, 500).is_err());
+        assert!(expand_output_template("{color}.png", &args, 500).is_err());
+    }
+
+    #[test]
+    fn chance_of_each_prize_grows_with_the_rolls() {
+        let aggregate = Simulator::default()
+            .simulate(
+                &mut SmallRng::seed_from_u64(TEST_SEED),
+                &SimulateOptions {
+                    first_earned: true,
+                    ..SimulateOptions::new(RunLimit::Runs(2000))
+                },
+                None,
+                &AtomicBool::new(false),
+            )
+            .unwrap();
+        let runs = aggregate.completed - aggregate.abandoned;
+
+        assert_eq!(
+            earned_within(&aggregate.first_earned, NUM_PRIZES, runs, 0),
+            [0.; NUM_PRIZES]
+        );
+        let mut previous = vec![0.; NUM_PRIZES];
+        for rolls in 1..=40 {
+            let probabilities = earned_within(&aggregate.first_earned, NUM_PRIZES, runs, rolls);
+            for (probability, previous) in probabilities.iter().zip(&previous) {
+                assert!(probability >= previous, "dropped at {} rolls", rolls);
+            }
+            previous = probabilities;
+        }
+        // Every finished run has every prize
+        assert_eq!(previous, [1.; NUM_PRIZES]);
+    }
+
+    #[test]
+    fn appending_twice_keeps_both_histograms() {
+        let path = temp_path("append.csv");
+        let _ = fs::remove_file(&path);
+        let first = analyze(&[8, 9, 9], &[]);
+        let second = analyze(&[10, 12], &[]);
+        append_histogram_csv(&path, &first).unwrap();
+        append_histogram_csv(&path, &second).unwrap();
 
-    let file_name = format!("output/{}-sim.png", runs);
-    let root = BitMapBackend::new(&file_name, (1280, 720)).into_drawing_area();
-    root.fill(&WHITE)?;
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let mut lines = contents.lines();
+        assert_eq!(lines.next(), Some(APPEND_CSV_HEADER));
+        let rows: Vec<(usize, usize)> = lines
+            .map(|line| {
+                let fields: Vec<_> = line.split(',').collect();
+                assert_eq!(fields.len(), 4, "{}", line);
+                (fields[2].parse().unwrap(), fields[3].parse().unwrap())
+            })
+            .collect();
+        assert_eq!(rows, [(8, 1), (9, 2), (10, 1), (12, 1)]);
+    }
+
+    #[test]
+    fn appending_to_a_foreign_csv_fails() {
+        let path = temp_path("foreign.csv");
+        fs::write(&path, "rolls\n8\n").unwrap();
+        let result = append_histogram_csv(&path, &analyze(&[8], &[]));
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(AppError::Config(_))));
+        assert_eq!(contents, "rolls\n8\n");
+    }
 
-    let mut chart_builder = ChartBuilder::on(&root);
-    chart_builder
-        .margin(5)
-        .set_label_area_size(LabelAreaPosition::Bottom, 50)
-        .set_label_area_size(LabelAreaPosition::Left, left_label_area_size)
-        .caption(
-            format!("Result of {} simulations of uma-bingo", runs),
-            ("Calibri", 36),
+    #[test]
+    fn days_round_the_rolls_up() {
+        let hist_data = HashMap::from([(8, 2), (10, 3), (11, 1), (26, 4)]);
+        assert_eq!(
+            days_histogram(&hist_data, 10),
+            HashMap::from([(1, 5), (2, 1), (3, 4)])
         );
+        assert_eq!(days_histogram(&hist_data, 1), hist_data);
 
-    let mut chart_context = chart_builder
-        .build_cartesian_2d((8..35 as usize).into_segmented(), 0..max_count + 5)
+        let aggregate = simulate(
+            &mut SmallRng::seed_from_u64(TEST_SEED),
+            &test_args("days", &[]),
+            None,
+            &AtomicBool::new(false),
+        )
         .unwrap();
-    chart_context
-        .configure_mesh()
-        .label_style(("Calibri", 28))
-        .x_desc("Number of rolls to earn all prizes")
-        .y_desc("Number of samples")
-        .draw()
+        let rolls = analyze_histogram(&aggregate.hist_data, &[]);
+        let days = analyze_histogram(&days_histogram(&aggregate.hist_data, 5), &[]);
+        assert_eq!(days.runs, rolls.runs);
+        assert_eq!(days.max, rolls.max.div_ceil(5));
+        assert_eq!(days.median, rolls.median.div_ceil(5));
+        // Rounding up adds less than a day to every run
+        assert!(days.mean >= rolls.mean / 5. && days.mean < rolls.mean / 5. + 1.);
+    }
+
+    #[test]
+    fn dumped_rolls_read_back_the_same() {
+        let aggregate = Simulator::default()
+            .simulate(
+                &mut SmallRng::seed_from_u64(TEST_SEED),
+                &SimulateOptions {
+                    roll_sequences: true,
+                    ..SimulateOptions::new(RunLimit::Runs(100))
+                },
+                None,
+                &AtomicBool::new(false),
+            )
+            .unwrap();
+        assert_eq!(aggregate.roll_sequences.len(), 100);
+
+        let path = temp_path("rolls.txt");
+        write_roll_sequences(&path, &aggregate.roll_sequences).unwrap();
+        let read_back = read_roll_sequences(&path, NUM_PRIZES);
+        fs::write(&path, "0 1 2\n3 8\n").unwrap();
+        let out_of_range = read_roll_sequences(&path, NUM_PRIZES);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(read_back.unwrap(), aggregate.roll_sequences);
+        assert!(
+            matches!(out_of_range, Err(AppError::Config(message)) if message.contains("line 2"))
+        );
+    }
+
+    #[test]
+    fn comparison_table_has_a_column_per_scenario() {
+        let hard = analyze(&[8, 10, 12, 20], &[]);
+        let pool = analyze(&[9, 11, 13, 21], &[]);
+        let scenarios = [("hard".to_string(), &hard), ("pool".to_string(), &pool)];
+        let table = comparison_table(&scenarios, 2, false);
+
+        let header: Vec<_> = table
+            .header()
+            .unwrap()
+            .cell_iter()
+            .map(|cell| cell.content())
+            .collect();
+        assert_eq!(header, ["", "hard", "pool"]);
+        assert!(table.row_iter().all(|row| row.cell_count() == 3));
+        assert_eq!(table_row(&table, "Mean"), ["Mean", "12.50", "13.50"]);
+        assert_eq!(
+            table_row(&table, "Mean vs first"),
+            ["Mean vs first", "+0.00", "+1.00"]
+        );
+        assert_eq!(table_row(&table, "Median"), ["Median", "10", "11"]);
+        assert_eq!(
+            table_row(&table, "95th percentile"),
+            ["95th percentile", "20", "21"]
+        );
+    }
+
+    #[test]
+    fn metrics_follow_the_prometheus_text_format() {
+        let path = temp_path("metrics.prom");
+        let summary = analyze(&[8, 9, 10, 12], &[50., 90.]);
+        write_metrics(&path, &summary, 5, 1).unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let is_name = |name: &str| {
+            !name.is_empty()
+                && !name.starts_with(|c: char| c.is_ascii_digit())
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+        };
+        let mut types = HashMap::new();
+        let mut samples = HashMap::new();
+        for line in contents.lines() {
+            if let Some(comment) = line.strip_prefix("# ") {
+                let mut words = comment.splitn(3, ' ');
+                let (keyword, name, rest) = (
+                    words.next().unwrap(),
+                    words.next().unwrap(),
+                    words.next().unwrap(),
+                );
+                assert!(is_name(name), "{}", line);
+                match keyword {
+                    "HELP" => {}
+                    "TYPE" => {
+                        assert!(
+                            ["counter", "gauge", "summary", "histogram", "untyped"].contains(&rest)
+                        );
+                        types.insert(name.to_string(), rest.to_string());
+                    }
+                    _ => panic!("unknown comment {}", line),
+                }
+                continue;
+            }
+
+            let (metric, value) = line.rsplit_once(' ').unwrap();
+            let value: f64 = value
+                .parse()
+                .unwrap_or_else(|_| panic!("bad value in {}", line));
+            let name = match metric.split_once('{') {
+                Some((name, labels)) => {
+                    let labels = labels.strip_suffix('}').unwrap();
+                    for label in labels.split(',') {
+                        let (key, label_value) = label.split_once('=').unwrap();
+                        assert!(is_name(key), "{}", line);
+                        assert!(
+                            label_value.len() >= 2
+                                && label_value.starts_with('"')
+                                && label_value.ends_with('"')
+                        );
+                    }
+                    name
+                }
+                None => metric,
+            };
+            assert!(is_name(name), "{}", line);
+            // The samples of a summary are named after it, with a _sum or _count suffix
+            let family = ["_sum", "_count"]
+                .iter()
+                .find_map(|suffix| {
+                    name.strip_suffix(suffix)
+                        .filter(|family| types.get(*family).is_some_and(|kind| kind == "summary"))
+                })
+                .unwrap_or(name);
+            assert!(types.contains_key(family), "{} has no TYPE", name);
+            samples.insert(metric.to_string(), value);
+        }
+
+        assert_eq!(samples["uma_bingo_runs"], 5.);
+        assert_eq!(samples["uma_bingo_abandoned_ratio"], 0.2);
+        assert_eq!(samples["uma_bingo_rolls_mean"], 9.75);
+        assert_eq!(samples["uma_bingo_rolls{quantile=\"0.5\"}"], 9.);
+        assert_eq!(samples["uma_bingo_rolls{quantile=\"0.9\"}"], 12.);
+        assert_eq!(samples["uma_bingo_rolls_sum"], 39.);
+        assert_eq!(samples["uma_bingo_rolls_count"], 4.);
+    }
+
+    #[test]
+    fn existing_output_is_kept_or_backed_up() {
+        let path = temp_path("existing.png");
+        let mut backup_path = path.clone().into_os_string();
+        backup_path.push(".bak");
+        let backup_path = PathBuf::from(backup_path);
+        let _ = fs::remove_file(&backup_path);
+
+        fs::write(&path, "old chart").unwrap();
+        let no_clobber = make_room_for_output(&path, &test_args("existing", &["--no-clobber"]));
+        assert!(matches!(no_clobber, Err(AppError::Io(_))));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old chart");
+
+        make_room_for_output(&path, &test_args("existing", &["--backup"])).unwrap();
+        assert!(!path.exists());
+        assert_eq!(fs::read_to_string(&backup_path).unwrap(), "old chart");
+        fs::remove_file(&backup_path).unwrap();
+
+        // Without either flag the file is left to be overwritten
+        fs::write(&path, "old chart").unwrap();
+        make_room_for_output(&path, &test_args("existing", &[])).unwrap();
+        assert!(path.exists());
+        fs::remove_file(&path).unwrap();
+
+        // Nothing to do when there is no file yet
+        make_room_for_output(&path, &test_args("existing", &["--no-clobber"])).unwrap();
+
+        let both: Vec<OsString> = ["uma-bingo", "--no-clobber", "--backup"]
+            .iter()
+            .map(OsString::from)
+            .collect();
+        assert!(parse_args(&both).is_err());
+    }
+
+    #[test]
+    fn term_chart_bars_are_proportional_to_the_counts() {
+        let summary = analyze_histogram(&HashMap::from([(8, 10), (9, 5), (10, 20), (11, 1)]), &[]);
+        let lines = term_chart_lines(&summary, 80);
+        assert_eq!(lines.len(), 4);
+
+        // The length of each bar in eighths of a column
+        let bar_eighths = |line: &str| -> usize {
+            let bar = line.split('│').nth(1).unwrap();
+            bar.chars()
+                .map(|c| match c {
+                    '█' => 8,
+                    '▏' => 1,
+                    '▎' => 2,
+                    '▍' => 3,
+                    '▌' => 4,
+                    '▋' => 5,
+                    '▊' => 6,
+                    '▉' => 7,
+                    _ => 0,
+                })
+                .sum()
+        };
+        // 80 columns leave 72 for the bars, so the longest bar is 576 eighths
+        let eighths: Vec<_> = lines.iter().map(|line| bar_eighths(line)).collect();
+        assert_eq!(eighths, [288, 144, 576, 28]);
+        assert!(lines.iter().all(|line| line.chars().count() == 79));
+        assert!(lines[2].starts_with("10 │") && lines[2].ends_with(" 20"));
+    }
+
+    #[test]
+    fn pity_savings_are_zero_without_pity() {
+        let summary = analyze(&[8, 20, 30, 40], &[]);
+        let interrupted = AtomicBool::new(false);
+
+        let (with_pity, without_pity) = pity_savings(
+            &test_args("no-pity", &["--pity-mode", "none"]),
+            &summary,
+            &interrupted,
+        )
         .unwrap();
+        assert_eq!(with_pity, without_pity);
+        assert_eq!(with_pity, summary.mean);
+
+        // On the default rules both are exact
+        let (with_pity, without_pity) =
+            pity_savings(&test_args("hard-pity", &[]), &summary, &interrupted).unwrap();
+        assert_eq!(
+            with_pity,
+            exact_mean_rolls(NUM_PRIZES, NUM_PRIZES, Some(PITY_THRESHOLD))
+        );
+        assert_eq!(without_pity, exact_mean_rolls(NUM_PRIZES, NUM_PRIZES, None));
+        assert!(with_pity < without_pity);
+
+        // A prize that can only come from pity makes the runs without it endless
+        let pity_only = test_args("pity-only", &["--weights", "1,1,1,1,1,1,1,0"]);
+        assert!(pity_savings(&pity_only, &summary, &interrupted).is_err());
+    }
+
+    #[test]
+    fn rarity_ranking_starts_with_the_rarest_prize() {
+        let args = test_args("rarity", &["--weights", "2,1,4,0.5,1,1,3,1"]);
+        let ranking = rarity_ranking(&args.sim_config());
+        let order: Vec<_> = ranking.iter().map(|&(prize_idx, _)| prize_idx).collect();
+        assert_eq!(order, [3, 1, 4, 5, 7, 0, 6, 2]);
+        assert_eq!(ranking[0], (3, 0.5 / 13.5));
+        assert_eq!(args.sim_config().weights.unwrap().rarest(), 3);
+
+        // Equally likely prizes stay in prize order
+        let ranking = rarity_ranking(&test_args("uniform-rarity", &[]).sim_config());
+        assert!(ranking
+            .iter()
+            .enumerate()
+            .all(|(rank, &(prize_idx, probability))| rank == prize_idx && probability == 0.125));
+    }
+
+    #[test]
+    fn sweep_renders_one_chart_per_value() {
+        let dir = temp_path("sweep");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+        let output = dir.join("chart.png");
+        let cli_args: Vec<OsString> = [
+            "uma-bingo",
+            "--runs",
+            "50",
+            "--rng",
+            "small",
+            "--seed",
+            "1",
+            "--sweep",
+            "pity=10,20,30",
+            "-o",
+        ]
+        .iter()
+        .map(OsString::from)
+        .chain([output.into_os_string()])
+        .collect();
+        let args = parse_args(&cli_args).unwrap();
+
+        run_sweep(&args, args.sweep.as_ref().unwrap(), &AtomicBool::new(false)).unwrap();
+        let mut files: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        fs::remove_dir_all(&dir).unwrap();
+        files.sort();
+        assert_eq!(
+            files,
+            [
+                "chart-pity-10.png",
+                "chart-pity-20.png",
+                "chart-pity-30.png"
+            ]
+        );
+    }
+
+    #[test]
+    fn max_memory_streams_the_rolls_only_when_they_could_not_fit() {
+        let dump = temp_path("dump.txt")
+            .into_os_string()
+            .into_string()
+            .unwrap();
+
+        // 200 runs take about 10 kB
+        assert!(!streams_rolls(&test_args(
+            "fits",
+            &["--dump-rolls", &dump, "--max-memory", "1"]
+        )));
+        assert!(!streams_rolls(&test_args(
+            "no-limit",
+            &["--dump-rolls", &dump]
+        )));
+        // 200 runs repeated 200 times take about 2 MB
+        assert!(streams_rolls(&test_args(
+            "too-big",
+            &[
+                "--dump-rolls",
+                &dump,
+                "--max-memory",
+                "1",
+                "--repeat",
+                "200"
+            ]
+        )));
+        // There's no telling how many runs a duration gets through
+        let timed = Args {
+            duration: Some(1.),
+            ..test_args("timed", &["--dump-rolls", &dump, "--max-memory", "1000"])
+        };
+        assert!(streams_rolls(&timed));
+    }
 
-    chart_context
-        .draw_series(
-            Histogram::vertical(&chart_context)
-                .style(BLUE.filled())
-                .margin(10)
-                .data(hist_data),
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn sqlite_export_reads_back_every_run() {
+        let aggregate = Simulator::default()
+            .simulate(
+                &mut SmallRng::seed_from_u64(TEST_SEED),
+                &SimulateOptions {
+                    run_records: true,
+                    ..SimulateOptions::new(RunLimit::Runs(300))
+                },
+                None,
+                &AtomicBool::new(false),
+            )
+            .unwrap();
+        let path = temp_path("runs.sqlite");
+        let _ = fs::remove_file(&path);
+        write_sqlite(&path, &aggregate.run_records).unwrap();
+        // Writing again replaces the table rather than adding to it
+        write_sqlite(&path, &aggregate.run_records).unwrap();
+
+        let connection = rusqlite::Connection::open(&path).unwrap();
+        let (count, total_rolls): (i64, i64) = connection
+            .query_row("SELECT COUNT(*), SUM(rolls) FROM runs", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        drop(connection);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(count, 300);
+        assert_eq!(total_rolls as usize, aggregate.total_rolls);
+    }
+
+    #[test]
+    fn page_presets_render_at_their_sizes() {
+        for (page, size) in [
+            (None, (1280, 720)),
+            (Some("a4"), (1280, 1810)),
+            (Some("letter"), (1280, 1656)),
+            (Some("square"), (1280, 1280)),
+        ] {
+            let args = match page {
+                Some(page) => test_args(&format!("page-{}", page), &["--page", page]),
+                None => test_args("no-page", &[]),
+            };
+            report(&args, &AtomicBool::new(false)).unwrap();
+            let dimensions = image::image_dimensions(&args.output_template);
+            fs::remove_file(&args.output_template).unwrap();
+            assert_eq!(dimensions.unwrap(), size, "{:?}", page);
+        }
+    }
+
+    #[test]
+    fn gof_needs_the_rules_of_the_exact_distribution() {
+        assert!(check_options(&test_args("gof", &["--gof"])).is_ok());
+        for extra in [&["--give-up", "100"][..], &["--blp-threshold", "30"]] {
+            let args = test_args("gof", &[&["--gof"], extra].concat());
+            assert!(matches!(check_options(&args), Err(AppError::Config(_))));
+        }
+    }
+
+    #[test]
+    fn html_report_is_titled_after_the_event() {
+        let args = test_args("html", &[]);
+        let summary = run_report(&args);
+        let options = HistogramOptions {
+            event_name: Some("Summer <Bingo>".to_string()),
+            ..args.histogram_options()
+        };
+        let path = temp_path("report.html");
+        write_html_report(
+            &path,
+            &summary,
+            args.precision,
+            args.chart_layout(),
+            args.transparent,
+            &options,
         )
         .unwrap();
+        let html = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(html.contains("<title>Result of 200 simulations of Summer &lt;Bingo&gt;</title>"));
+        assert!(html.contains("<h1>Result of 200 simulations of Summer &lt;Bingo&gt;</h1>"));
+    }
 
-    Ok(())
+    #[test]
+    fn prize_options_follow_the_event() {
+        let event_path = temp_path("ten-prizes.toml");
+        fs::write(&event_path, "prizes = 10\n").unwrap();
+        let event = event_path.to_str().unwrap();
+        let with_event =
+            |extra: &[&str]| test_args("ten-prizes", &[&["--event", event], extra].concat());
+
+        assert!(check_options(&with_event(&["--remaining", "1111111110:30"])).is_ok());
+        assert!(check_options(&with_event(&["--prob-last", "10"])).is_ok());
+        for extra in [&["--remaining", "11111110:30"][..], &["--prob-last", "11"]] {
+            assert!(matches!(
+                check_options(&with_event(extra)),
+                Err(AppError::Config(_))
+            ));
+        }
+        // Without the event, the same options are checked against the 8 default prizes
+        assert!(check_options(&test_args("eight-prizes", &["--prob-last", "10"])).is_err());
+
+        let summary = run_report(&with_event(&[]));
+        fs::remove_file(&event_path).unwrap();
+        assert!(summary.min >= 10);
+    }
 }
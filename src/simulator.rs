@@ -0,0 +1,1507 @@
+//! An API for simulating many runs and aggregating them, for tools that embed the simulation
+//!
+//! A [`PrizePool`] says which prizes an event hands out, and a [`Simulator`] adds the pity rules
+//! and runs the simulation:
+//!
+//! ```
+//! use std::sync::atomic::AtomicBool;
+//!
+//! use rand::{rngs::SmallRng, SeedableRng};
+//! use uma_bingo::simulator::{PrizePool, RunLimit, SimulateOptions, Simulator};
+//! use uma_bingo::PityMode;
+//!
+//! let pool = PrizePool::new(6).unwrap().with_copies(&[1, 1, 1, 1, 1, 2]).unwrap();
+//! let simulator = Simulator::new(&pool).with_pity(PityMode::Hard, 20);
+//! let aggregate = simulator
+//!     .simulate(
+//!         &mut SmallRng::seed_from_u64(0),
+//!         &SimulateOptions::new(RunLimit::Runs(1000)),
+//!         None,
+//!         &AtomicBool::new(false),
+//!     )
+//!     .unwrap();
+//! let summary = aggregate.summary(&[90.]).unwrap();
+//! assert!(summary.min >= 7);
+//! ```
+
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{self, Write},
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use rand::Rng;
+
+use crate::{
+    analyze_histogram, new_prize_gaps, new_prize_rolls, run_sim_detailed, simulate_session,
+    AntitheticPairs, Autocorrelation, PityMode, PrizeSets, PrizeWeights, RollResult, RunDetail,
+    SimConfig, StatsSummary, NUM_PRIZES,
+};
+
+/// The prizes an event hands out: how many there are, how likely each one is and how many copies
+/// of each are needed
+#[derive(Clone, Debug)]
+pub struct PrizePool {
+    prizes: usize,
+    /// The weight of each prize, or `None` when they are equally likely
+    weights: Option<PrizeWeights>,
+    copies: Vec<usize>,
+}
+
+impl PrizePool {
+    /// `prizes` equally likely prizes that are each needed once
+    pub fn new(prizes: usize) -> Result<Self, String> {
+        if prizes == 0 {
+            return Err("an event needs at least 1 prize".to_string());
+        }
+
+        Ok(PrizePool {
+            prizes,
+            weights: None,
+            copies: vec![1; prizes],
+        })
+    }
+
+    /// Roll the prizes in proportion to `weights`, one for each prize
+    pub fn with_weights(mut self, weights: &[f64]) -> Result<Self, String> {
+        if weights.len() != self.prizes {
+            return Err(format!(
+                "expected {} weights, one per prize, got {}",
+                self.prizes,
+                weights.len()
+            ));
+        }
+        self.weights = Some(PrizeWeights::new(weights.to_vec())?);
+        Ok(self)
+    }
+
+    /// Need `copies[i]` copies of prize `i` before it counts as earned, one count for each prize
+    pub fn with_copies(mut self, copies: &[usize]) -> Result<Self, String> {
+        if copies.len() != self.prizes {
+            return Err(format!(
+                "expected {} copy counts, one per prize, got {}",
+                self.prizes,
+                copies.len()
+            ));
+        }
+        if copies.contains(&0) {
+            return Err("every prize needs at least 1 copy".to_string());
+        }
+        self.copies = copies.to_vec();
+        Ok(self)
+    }
+
+    /// How many prizes the pool has
+    pub fn prizes(&self) -> usize {
+        self.prizes
+    }
+
+    /// Make `config` roll this pool's prizes and finish once every one of them is earned. The
+    /// other rules of `config`, like the pity mode, stay as they are
+    pub fn apply(&self, config: &mut SimConfig) {
+        config.prizes = self.prizes;
+        config.sets = PrizeSets::first(self.prizes);
+        config.until = None;
+        config.deck_size = None;
+        config.weights = self.weights.clone();
+        config.copies = self.copies.clone();
+    }
+}
+
+impl Default for PrizePool {
+    /// The default rules of eight equally likely prizes that are each needed once
+    fn default() -> Self {
+        PrizePool::new(NUM_PRIZES).unwrap() // okay to unwrap because NUM_PRIZES prizes always fit
+    }
+}
+
+/// Something that may happen in the first rolls of a run, which [`SimulateOptions::condition_on`]
+/// splits the runs by
+#[derive(Clone, Copy, Debug)]
+pub enum EarlyEvent {
+    /// A prize was rolled twice within the first this many rolls
+    DuplicateWithin(usize),
+    /// At least `prizes` different prizes were earned within the first `rolls` rolls
+    PrizesWithin { rolls: usize, prizes: usize },
+}
+
+impl EarlyEvent {
+    /// Whether the event happened in the run that rolled `sim_res`
+    pub fn happened(self, sim_res: &[RollResult]) -> bool {
+        let rolls = match self {
+            EarlyEvent::DuplicateWithin(rolls) | EarlyEvent::PrizesWithin { rolls, .. } => rolls,
+        };
+        let early_rolls = &sim_res[..rolls.min(sim_res.len())];
+        let new_prizes = new_prize_rolls(early_rolls).count();
+
+        match self {
+            EarlyEvent::DuplicateWithin(_) => new_prizes < early_rolls.len(),
+            EarlyEvent::PrizesWithin { prizes, .. } => new_prizes >= prizes,
+        }
+    }
+
+    /// Describe the runs where the event did and didn't happen
+    pub fn descriptions(self) -> (String, String) {
+        match self {
+            EarlyEvent::DuplicateWithin(rolls) => (
+                format!("Runs with a duplicate in the first {} rolls", rolls),
+                format!("Runs without a duplicate in the first {} rolls", rolls),
+            ),
+            EarlyEvent::PrizesWithin { rolls, prizes } => (
+                format!(
+                    "Runs with at least {} prizes in the first {} rolls",
+                    prizes, rolls
+                ),
+                format!(
+                    "Runs with fewer than {} prizes in the first {} rolls",
+                    prizes, rolls
+                ),
+            ),
+        }
+    }
+}
+
+/// When [`Simulator::simulate`] stops
+#[derive(Clone, Copy, Debug)]
+pub enum RunLimit {
+    /// After this many runs, counting the ones that gave up
+    Runs(usize),
+    /// Once this much time has passed
+    Duration(Duration),
+}
+
+/// How many runs to complete between clock checks when simulating for a duration
+const CLOCK_CHECK_INTERVAL: usize = 1024;
+
+/// How long to simulate and which of the optional parts of an [`Aggregate`] to keep. Only the
+/// histogram and the totals are kept by default, since the rest costs time or memory every run
+#[derive(Clone, Debug)]
+pub struct SimulateOptions {
+    pub limit: RunLimit,
+    /// Simulate the runs in antithetic pairs and keep the averages of the pairs
+    pub variance_reduction: bool,
+    /// Keep [`Aggregate::first_earned`]
+    pub first_earned: bool,
+    /// Keep [`Aggregate::collection_order`]
+    pub collection_order: bool,
+    /// Keep [`Aggregate::last_earned`]
+    pub last_earned: bool,
+    /// Keep [`Aggregate::roll_sequences`], unless a roll sink is given
+    pub roll_sequences: bool,
+    /// Keep [`Aggregate::run_lengths`]
+    pub run_lengths: bool,
+    /// Keep [`Aggregate::gap_totals`] and [`Aggregate::gap_counts`]
+    pub gaps: bool,
+    /// Keep [`Aggregate::autocorrelation`]
+    pub autocorrelation: bool,
+    /// Keep [`Aggregate::hist_with_pity`] and [`Aggregate::hist_without_pity`]
+    pub split_by_pity: bool,
+    /// Keep [`Aggregate::hist_with_event`] and [`Aggregate::hist_without_event`] for this event
+    pub condition_on: Option<EarlyEvent>,
+    /// Keep [`Aggregate::run_records`]
+    #[cfg(feature = "sqlite")]
+    pub run_records: bool,
+}
+
+impl SimulateOptions {
+    /// Simulate until `limit`, keeping only the histogram and the totals
+    pub fn new(limit: RunLimit) -> Self {
+        SimulateOptions {
+            limit,
+            variance_reduction: false,
+            first_earned: false,
+            collection_order: false,
+            last_earned: false,
+            roll_sequences: false,
+            run_lengths: false,
+            gaps: false,
+            autocorrelation: false,
+            split_by_pity: false,
+            condition_on: None,
+            #[cfg(feature = "sqlite")]
+            run_records: false,
+        }
+    }
+}
+
+/// What a batch of runs added up to. The optional parts stay empty unless [`SimulateOptions`]
+/// asks for them
+#[derive(Debug, Default)]
+pub struct Aggregate {
+    pub hist_data: HashMap<usize, usize>,
+    /// `first_earned[roll][prize]` counts the finished runs that first earned `prize` on roll
+    /// `roll + 1`. Every row has an entry per prize
+    pub first_earned: Vec<Vec<usize>>,
+    /// `collection_order[prize][k]` counts the runs where `prize` was the k-th prize collected,
+    /// with a row of an entry per prize for each prize
+    pub collection_order: Vec<Vec<usize>>,
+    /// `last_earned[prize]` counts the finished runs whose last new prize was `prize`
+    pub last_earned: Vec<usize>,
+    /// Rolls of the runs that finished
+    pub total_rolls: usize,
+    pub total_rng_draws: usize,
+    /// Every run simulated, including the abandoned ones
+    pub completed: usize,
+    /// Runs that gave up before finishing. They aren't in any of the other stats
+    pub abandoned: usize,
+    /// Every roll of every finished run
+    pub roll_sequences: Vec<Vec<RollResult>>,
+    /// The number of rolls of every finished run
+    pub run_lengths: Vec<u32>,
+    /// `gap_totals[k]` sums the rolls it took to go from k + 1 different prizes to k + 2, over
+    /// the `gap_counts[k]` runs that got there. There is an entry for every prize but the first
+    pub gap_totals: Vec<usize>,
+    pub gap_counts: Vec<usize>,
+    /// The autocorrelation of the rolls of consecutive finished runs
+    pub autocorrelation: Autocorrelation,
+    /// The histograms of the finished runs that did and didn't get a prize from pity
+    pub hist_with_pity: HashMap<usize, usize>,
+    pub hist_without_pity: HashMap<usize, usize>,
+    /// The histograms of the finished runs where the early event did and didn't happen
+    pub hist_with_event: HashMap<usize, usize>,
+    pub hist_without_event: HashMap<usize, usize>,
+    /// How many antithetic pairs finished, and the sum and the sum of squares of their averages
+    pub pairs: usize,
+    pub pair_total: f64,
+    pub pair_total_squares: f64,
+    /// Every finished run in order
+    #[cfg(feature = "sqlite")]
+    pub run_records: Vec<RunRecord>,
+}
+
+/// One finished run, as kept for [`SimulateOptions::run_records`]
+#[cfg(feature = "sqlite")]
+#[derive(Clone, Debug)]
+pub struct RunRecord {
+    pub rolls: usize,
+    /// Rolls beyond the copies each prize needed
+    pub duplicates: usize,
+    pub pity_used: bool,
+}
+
+/// Add each of `other_counts` to the count at the same index, growing `counts` to fit
+fn add_counts(counts: &mut Vec<usize>, other_counts: Vec<usize>) {
+    if counts.len() < other_counts.len() {
+        counts.resize(other_counts.len(), 0);
+    }
+    for (count, other_count) in counts.iter_mut().zip(other_counts) {
+        *count += other_count;
+    }
+}
+
+/// [`add_counts`] for every row of `other_rows`, growing `rows` to fit
+fn add_count_rows(rows: &mut Vec<Vec<usize>>, other_rows: Vec<Vec<usize>>) {
+    if rows.len() < other_rows.len() {
+        rows.resize(other_rows.len(), Vec::new());
+    }
+    for (counts, other_counts) in rows.iter_mut().zip(other_rows) {
+        add_counts(counts, other_counts);
+    }
+}
+
+impl Aggregate {
+    /// Fold the results of another set of runs into this one
+    pub fn merge(&mut self, other: Aggregate) {
+        for (rolls, count) in other.hist_data {
+            *self.hist_data.entry(rolls).or_insert(0) += count;
+        }
+        add_count_rows(&mut self.first_earned, other.first_earned);
+        add_count_rows(&mut self.collection_order, other.collection_order);
+        add_counts(&mut self.last_earned, other.last_earned);
+        self.total_rolls += other.total_rolls;
+        self.total_rng_draws += other.total_rng_draws;
+        self.completed += other.completed;
+        self.abandoned += other.abandoned;
+        self.roll_sequences.extend(other.roll_sequences);
+        self.run_lengths.extend(other.run_lengths);
+        add_counts(&mut self.gap_totals, other.gap_totals);
+        add_counts(&mut self.gap_counts, other.gap_counts);
+        self.autocorrelation.append(&other.autocorrelation);
+        for (rolls, count) in other.hist_with_pity {
+            *self.hist_with_pity.entry(rolls).or_insert(0) += count;
+        }
+        for (rolls, count) in other.hist_without_pity {
+            *self.hist_without_pity.entry(rolls).or_insert(0) += count;
+        }
+        for (rolls, count) in other.hist_with_event {
+            *self.hist_with_event.entry(rolls).or_insert(0) += count;
+        }
+        for (rolls, count) in other.hist_without_event {
+            *self.hist_without_event.entry(rolls).or_insert(0) += count;
+        }
+        self.pairs += other.pairs;
+        self.pair_total += other.pair_total;
+        self.pair_total_squares += other.pair_total_squares;
+        #[cfg(feature = "sqlite")]
+        self.run_records.extend(other.run_records);
+    }
+
+    /// The summary statistics of the finished runs, or `None` if none of them finished
+    pub fn summary(&self, percentiles: &[f64]) -> Option<StatsSummary> {
+        (self.completed > self.abandoned).then(|| analyze_histogram(&self.hist_data, percentiles))
+    }
+
+    /// The standard error of the average number of rolls from the averages of the antithetic
+    /// pairs, or `None` with fewer than 2 pairs
+    pub fn pair_standard_error(&self) -> Option<f64> {
+        if self.pairs < 2 {
+            return None;
+        }
+        let pairs = self.pairs as f64;
+        let pair_mean = self.pair_total / pairs;
+        let pair_variance =
+            (self.pair_total_squares - pairs * pair_mean * pair_mean) / (pairs - 1.);
+        Some((pair_variance.max(0.) / pairs).sqrt())
+    }
+}
+
+/// Simulates runs of a [`PrizePool`] under the pity rules
+///
+/// This is a thin layer over [`SimConfig`] and [`run_sim_detailed`], so any rules a config can
+/// express can be simulated with [`Simulator::from`] a config.
+#[derive(Clone, Debug, Default)]
+pub struct Simulator {
+    config: SimConfig,
+}
+
+impl Simulator {
+    /// Simulate `pool` with the default pity rules
+    pub fn new(pool: &PrizePool) -> Self {
+        let mut config = SimConfig::default();
+        pool.apply(&mut config);
+        Simulator { config }
+    }
+
+    /// Use `pity_mode` after `pity_threshold` rolls
+    pub fn with_pity(mut self, pity_mode: PityMode, pity_threshold: usize) -> Self {
+        self.config.pity_mode = pity_mode;
+        self.config.pity_threshold = pity_threshold;
+        self
+    }
+
+    /// Give up on a run after `give_up` rolls, or never when it's `None`
+    pub fn with_give_up(mut self, give_up: Option<usize>) -> Self {
+        self.config.give_up = give_up;
+        self
+    }
+
+    /// The rules the runs are simulated with
+    pub fn config(&self) -> &SimConfig {
+        &self.config
+    }
+
+    /// Check that every run is sure to finish, see [`SimConfig::validate`]
+    pub fn validate(&self) -> Result<(), String> {
+        self.config.validate()
+    }
+
+    /// Simulate a single run
+    pub fn run<R: Rng + ?Sized>(&self, rng: &mut R) -> RunDetail {
+        run_sim_detailed(&self.config, rng)
+    }
+
+    /// Simulate runs until `options.limit`, stopping early if `interrupted` gets set
+    ///
+    /// With a `roll_sink`, the rolls of every finished run are written to it as the runs finish
+    /// instead of being kept in the aggregate.
+    pub fn simulate<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        options: &SimulateOptions,
+        mut roll_sink: Option<&mut dyn Write>,
+        interrupted: &AtomicBool,
+    ) -> io::Result<Aggregate> {
+        let start = Instant::now();
+        let config = &self.config;
+
+        // Aggregate each run as it finishes so that the stats are valid whenever we stop
+        let mut aggregate = Aggregate::default();
+        if options.collection_order {
+            aggregate.collection_order = vec![vec![0; config.prizes]; config.prizes];
+        }
+        if options.last_earned {
+            aggregate.last_earned = vec![0; config.prizes];
+        }
+        if options.gaps {
+            aggregate.gap_totals = vec![0; config.prizes - 1];
+            aggregate.gap_counts = vec![0; config.prizes - 1];
+        }
+        let mut antithetic_pairs = AntitheticPairs::new();
+        let mut first_of_pair = 0;
+        while !interrupted.load(Ordering::Relaxed) {
+            let done = match options.limit {
+                // Reading the clock every run would be a noticeable part of the loop
+                RunLimit::Duration(duration) => {
+                    aggregate.completed % CLOCK_CHECK_INTERVAL == 0 && start.elapsed() >= duration
+                }
+                RunLimit::Runs(runs) => aggregate.completed >= runs,
+            };
+            if done {
+                break;
+            }
+
+            let RunDetail {
+                rolls: sim_res,
+                rng_draws,
+                finished,
+                pity_rolls,
+            } = if options.variance_reduction {
+                if antithetic_pairs.completes_pair() {
+                    let rolls = antithetic_pairs.run_sim_detailed(config, rng);
+                    let pair_average = (first_of_pair + rolls.rolls.len()) as f64 / 2.;
+                    aggregate.pairs += 1;
+                    aggregate.pair_total += pair_average;
+                    aggregate.pair_total_squares += pair_average * pair_average;
+                    rolls
+                } else {
+                    let rolls = antithetic_pairs.run_sim_detailed(config, rng);
+                    first_of_pair = rolls.rolls.len();
+                    rolls
+                }
+            } else {
+                run_sim_detailed(config, rng)
+            };
+            aggregate.total_rng_draws += rng_draws;
+            aggregate.completed += 1;
+            if !finished {
+                aggregate.abandoned += 1;
+                continue;
+            }
+
+            aggregate.total_rolls += sim_res.len();
+            aggregate
+                .hist_data
+                .entry(sim_res.len())
+                .and_modify(|count| *count += 1)
+                .or_insert(1);
+            if options.first_earned {
+                record_first_earned(&mut aggregate.first_earned, config.prizes, &sim_res);
+            }
+            if options.collection_order {
+                record_collection_order(&mut aggregate.collection_order, &sim_res);
+            }
+            if options.run_lengths {
+                aggregate.run_lengths.push(sim_res.len() as u32);
+            }
+            if options.last_earned {
+                if let Some(last_prize) = last_new_prize(&sim_res) {
+                    aggregate.last_earned[last_prize] += 1;
+                }
+            }
+            if options.autocorrelation {
+                aggregate.autocorrelation.push(sim_res.len());
+            }
+            if options.split_by_pity {
+                let hist_data = if pity_rolls > 0 {
+                    &mut aggregate.hist_with_pity
+                } else {
+                    &mut aggregate.hist_without_pity
+                };
+                *hist_data.entry(sim_res.len()).or_insert(0) += 1;
+            }
+            if let Some(early_event) = options.condition_on {
+                let hist_data = if early_event.happened(&sim_res) {
+                    &mut aggregate.hist_with_event
+                } else {
+                    &mut aggregate.hist_without_event
+                };
+                *hist_data.entry(sim_res.len()).or_insert(0) += 1;
+            }
+            if options.gaps {
+                for (transition, gap) in new_prize_gaps(&sim_res).into_iter().enumerate() {
+                    aggregate.gap_totals[transition] += gap;
+                    aggregate.gap_counts[transition] += 1;
+                }
+            }
+            #[cfg(feature = "sqlite")]
+            if options.run_records {
+                aggregate.run_records.push(RunRecord {
+                    rolls: sim_res.len(),
+                    duplicates: duplicate_rolls(config, &sim_res),
+                    pity_used: pity_rolls > 0,
+                });
+            }
+            if let Some(roll_sink) = roll_sink.as_deref_mut() {
+                write_roll_sequence(roll_sink, &sim_res)?;
+            } else if options.roll_sequences {
+                aggregate.roll_sequences.push(sim_res);
+            }
+        }
+
+        Ok(aggregate)
+    }
+
+    /// Share the runs out over `threads` threads, each simulating its share with the generator
+    /// `new_rng` makes for its thread index, and merge what they aggregated. With a
+    /// [`RunLimit::Duration`], every thread runs for the whole duration
+    pub fn simulate_in_parallel<R, F>(
+        &self,
+        options: &SimulateOptions,
+        threads: usize,
+        new_rng: F,
+        interrupted: &AtomicBool,
+    ) -> io::Result<Aggregate>
+    where
+        R: Rng,
+        F: Fn(usize) -> R + Sync,
+    {
+        let threads = threads.max(1);
+        thread::scope(|scope| {
+            let handles: Vec<_> = (0..threads)
+                .map(|thread_idx| {
+                    let mut thread_options = options.clone();
+                    if let RunLimit::Runs(runs) = options.limit {
+                        thread_options.limit = RunLimit::Runs(
+                            runs / threads + usize::from(thread_idx < runs % threads),
+                        );
+                    }
+                    let new_rng = &new_rng;
+                    scope.spawn(move || {
+                        self.simulate(&mut new_rng(thread_idx), &thread_options, None, interrupted)
+                    })
+                })
+                .collect();
+
+            let mut aggregate = Aggregate::default();
+            for handle in handles {
+                // A panic in a thread is a bug, so pass it on instead of reporting it as an I/O
+                // error
+                let thread_aggregate = handle
+                    .join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic))?;
+                aggregate.merge(thread_aggregate);
+            }
+            Ok(aggregate)
+        })
+    }
+
+    /// The average number of rolls of up to `runs` runs, leaving out the ones that gave up, or
+    /// `None` if none of them finished
+    pub fn mean_rolls<R: Rng + ?Sized>(
+        &self,
+        runs: usize,
+        rng: &mut R,
+        interrupted: &AtomicBool,
+    ) -> Option<f64> {
+        let mut total_rolls = 0;
+        let mut finished = 0;
+        for _ in 0..runs {
+            if interrupted.load(Ordering::Relaxed) {
+                break;
+            }
+            let run = self.run(rng);
+            if run.finished {
+                total_rolls += run.rolls.len();
+                finished += 1;
+            }
+        }
+
+        (finished > 0).then(|| total_rolls as f64 / finished as f64)
+    }
+
+    /// Simulate once with each of `pity_modes`, every time with a generator from `new_rng` so
+    /// that they can all start from the same seed, and name each histogram after its mode
+    ///
+    /// Stops at the mode that was simulating when `interrupted` got set, leaving it out.
+    pub fn pity_mode_histograms<R: Rng>(
+        &self,
+        pity_modes: &[PityMode],
+        options: &SimulateOptions,
+        mut new_rng: impl FnMut() -> R,
+        interrupted: &AtomicBool,
+    ) -> io::Result<Vec<NamedHistogram>> {
+        let mut histograms = Vec::new();
+        for &pity_mode in pity_modes {
+            let aggregate = self
+                .clone()
+                .with_pity(pity_mode, self.config.pity_threshold)
+                .simulate(&mut new_rng(), options, None, interrupted)?;
+            if interrupted.load(Ordering::Relaxed) {
+                break;
+            }
+            histograms.push((format!("pity-mode={}", pity_mode), aggregate.hist_data));
+        }
+
+        Ok(histograms)
+    }
+
+    /// [`Simulator::pity_mode_histograms`] along with the summary statistics of each mode
+    pub fn compare_pity<R: Rng>(
+        &self,
+        pity_modes: &[PityMode],
+        options: &SimulateOptions,
+        percentiles: &[f64],
+        new_rng: impl FnMut() -> R,
+        interrupted: &AtomicBool,
+    ) -> Result<Vec<(NamedHistogram, StatsSummary)>, CompareError> {
+        self.pity_mode_histograms(pity_modes, options, new_rng, interrupted)?
+            .into_iter()
+            .map(|(name, hist_data)| {
+                let summary = summarize_finished(&hist_data, percentiles, &name)?;
+                Ok(((name, hist_data), summary))
+            })
+            .collect()
+    }
+
+    /// Simulate once for each of `seeds` seeds, with the generator `rng_for_seed` makes for the
+    /// seed's index, and return their histograms
+    ///
+    /// A seed cut short by `interrupted` would look like an outlier, so it's left out.
+    pub fn compare_seeds<R: Rng>(
+        &self,
+        seeds: u64,
+        options: &SimulateOptions,
+        mut rng_for_seed: impl FnMut(u64) -> R,
+        interrupted: &AtomicBool,
+    ) -> Result<Vec<HashMap<usize, usize>>, CompareError> {
+        let mut histograms = Vec::new();
+        for seed_idx in 0..seeds {
+            let aggregate =
+                self.simulate(&mut rng_for_seed(seed_idx), options, None, interrupted)?;
+            if interrupted.load(Ordering::Relaxed) {
+                break;
+            }
+            histograms.push(aggregate.hist_data);
+        }
+
+        if !histograms.is_empty() && histograms.iter().all(HashMap::is_empty) {
+            return Err(CompareError::NoneFinished("any seed".to_string()));
+        }
+        Ok(histograms)
+    }
+
+    /// Simulate `sessions` sessions of `pulls` pulls from nothing earned and count how many new
+    /// prizes each of them earned. Stops early if `interrupted` gets set
+    pub fn session_stats<R: Rng + ?Sized>(
+        &self,
+        pulls: usize,
+        sessions: usize,
+        rng: &mut R,
+        interrupted: &AtomicBool,
+    ) -> SessionStats {
+        let config = &self.config;
+        let mut new_prize_counts = vec![0; config.prizes + 1];
+        for _ in 0..sessions {
+            if interrupted.load(Ordering::Relaxed) {
+                break;
+            }
+            let new_prizes = simulate_session(config, &vec![false; config.prizes], pulls, rng);
+            new_prize_counts[new_prizes] += 1;
+        }
+
+        SessionStats { new_prize_counts }
+    }
+
+    /// Binary search for the pity threshold whose average number of rolls over `runs` runs is
+    /// closest to `target_mean`, or `None` if `interrupted` got set
+    ///
+    /// Every threshold is simulated with a generator from `new_rng`, which should be seeded the
+    /// same way every time, so that the averages of neighboring thresholds differ only because of
+    /// the threshold and keep increasing with it.
+    pub fn solve_pity_for_mean<R: Rng>(
+        &self,
+        runs: usize,
+        target_mean: f64,
+        mut new_rng: impl FnMut() -> R,
+        interrupted: &AtomicBool,
+    ) -> Result<Option<PitySolution>, CompareError> {
+        if self.config.pity_mode == PityMode::None {
+            return Err(CompareError::Config(
+                "solving for a pity threshold needs a pity mode other than none".to_string(),
+            ));
+        }
+        let mut means = HashMap::new();
+        let mut mean_with_threshold = |pity_threshold| -> Result<Option<f64>, CompareError> {
+            if let Some(&mean) = means.get(&pity_threshold) {
+                return Ok(Some(mean));
+            }
+            let simulator = self
+                .clone()
+                .with_pity(self.config.pity_mode, pity_threshold);
+            let mean = match simulator.mean_rolls(runs, &mut new_rng(), interrupted) {
+                Some(mean) => mean,
+                None if interrupted.load(Ordering::Relaxed) => return Ok(None),
+                None => {
+                    return Err(CompareError::NoneFinished(format!(
+                        "a pity threshold of {}",
+                        pity_threshold
+                    )))
+                }
+            };
+            means.insert(pity_threshold, mean);
+            Ok(Some(mean))
+        };
+
+        // Find the lowest threshold whose average reaches the target
+        let mut low = 0;
+        let mut high = MAX_SOLVED_PITY_THRESHOLD;
+        while low < high {
+            let mid = (low + high) / 2;
+            let Some(mean) = mean_with_threshold(mid)? else {
+                return Ok(None);
+            };
+            if mean < target_mean {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        let Some(mean) = mean_with_threshold(low)? else {
+            return Ok(None);
+        };
+        let mut closest = (low, mean);
+        if low > 0 {
+            let Some(below) = mean_with_threshold(low - 1)? else {
+                return Ok(None);
+            };
+            if (below - target_mean).abs() < (closest.1 - target_mean).abs() {
+                closest = (low - 1, below);
+            }
+        }
+        if interrupted.load(Ordering::Relaxed) {
+            return Ok(None);
+        }
+
+        let (pity_threshold, mean) = closest;
+        Ok(Some(PitySolution {
+            pity_threshold,
+            mean,
+            reaches_target: !(pity_threshold == 0 && mean > target_mean
+                || pity_threshold == MAX_SOLVED_PITY_THRESHOLD && mean < target_mean),
+        }))
+    }
+
+    /// Simulate `runs` runs for every prize count from 1 to all of the prizes and summarize each
+    /// of them. Each count keeps the rules of its prizes and has to collect all of them
+    ///
+    /// Stops at the count that was simulating when `interrupted` got set, leaving it out.
+    pub fn simulate_prize_counts<R: Rng + ?Sized>(
+        &self,
+        runs: usize,
+        rng: &mut R,
+        interrupted: &AtomicBool,
+    ) -> Result<Vec<(usize, StatsSummary)>, CompareError> {
+        let options = SimulateOptions::new(RunLimit::Runs(runs));
+        let mut scenarios = Vec::new();
+        for num_prizes in 1..=self.config.prizes {
+            let simulator = Simulator::from(
+                first_prizes_config(&self.config, num_prizes).map_err(CompareError::Config)?,
+            );
+            let aggregate = simulator.simulate(rng, &options, None, interrupted)?;
+            if interrupted.load(Ordering::Relaxed) {
+                break;
+            }
+            let name = format!("{} prizes", num_prizes);
+            scenarios.push((
+                num_prizes,
+                summarize_finished(&aggregate.hist_data, &[], &name)?,
+            ));
+        }
+
+        Ok(scenarios)
+    }
+}
+
+impl From<SimConfig> for Simulator {
+    fn from(config: SimConfig) -> Self {
+        Simulator { config }
+    }
+}
+
+/// A histogram along with the name it goes by in tables and legends
+pub type NamedHistogram = (String, HashMap<usize, usize>);
+
+/// Why the scenarios of a comparison couldn't be simulated or summarized
+#[derive(Debug)]
+pub enum CompareError {
+    Io(io::Error),
+    /// The rules of a scenario can't be simulated
+    Config(String),
+    /// None of the runs of the named scenario finished, so there is nothing to compare it by
+    NoneFinished(String),
+}
+
+impl fmt::Display for CompareError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompareError::Io(err) => write!(f, "{}", err),
+            CompareError::Config(err) => write!(f, "{}", err),
+            CompareError::NoneFinished(scenario) => write!(
+                f,
+                "no run of {} finished, so there is nothing to analyze",
+                scenario
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CompareError {}
+
+impl From<io::Error> for CompareError {
+    fn from(err: io::Error) -> Self {
+        CompareError::Io(err)
+    }
+}
+
+/// How many new prizes each of a number of sessions earned, see [`Simulator::session_stats`]
+#[derive(Clone, Debug)]
+pub struct SessionStats {
+    /// `new_prize_counts[k]` counts the sessions that earned `k` new prizes
+    pub new_prize_counts: Vec<usize>,
+}
+
+impl SessionStats {
+    /// How many sessions were simulated
+    pub fn sessions(&self) -> usize {
+        self.new_prize_counts.iter().sum()
+    }
+
+    /// The average number of new prizes a session earned, or `None` without any sessions
+    pub fn mean_new_prizes(&self) -> Option<f64> {
+        let sessions = self.sessions();
+        let total_new_prizes: usize = self
+            .new_prize_counts
+            .iter()
+            .enumerate()
+            .map(|(new_prizes, count)| new_prizes * count)
+            .sum();
+        (sessions > 0).then(|| total_new_prizes as f64 / sessions as f64)
+    }
+}
+
+/// The highest pity threshold [`Simulator::solve_pity_for_mean`] tries. Hardly any run gets this
+/// far without pity, so higher thresholds give the same average
+pub const MAX_SOLVED_PITY_THRESHOLD: usize = 200;
+
+/// The pity threshold whose average number of rolls is closest to a target
+#[derive(Clone, Copy, Debug)]
+pub struct PitySolution {
+    pub pity_threshold: usize,
+    /// The average number of rolls with the threshold
+    pub mean: f64,
+    /// Whether a threshold gets to the target, rather than this being the closest of the ones
+    /// that all miss it
+    pub reaches_target: bool,
+}
+
+/// The summary statistics of `hist_data`, or an error naming `scenario` if none of its runs
+/// finished
+fn summarize_finished(
+    hist_data: &HashMap<usize, usize>,
+    percentiles: &[f64],
+    scenario: &str,
+) -> Result<StatsSummary, CompareError> {
+    if hist_data.values().all(|&count| count == 0) {
+        return Err(CompareError::NoneFinished(scenario.to_string()));
+    }
+    Ok(analyze_histogram(hist_data, percentiles))
+}
+
+/// The rules of `config` for only its first `num_prizes` prizes, which all have to be collected
+fn first_prizes_config(config: &SimConfig, num_prizes: usize) -> Result<SimConfig, String> {
+    let mut pool = PrizePool::new(num_prizes)?.with_copies(&config.copies[..num_prizes])?;
+    if let Some(weights) = &config.weights {
+        pool = pool.with_weights(&weights.weights()[..num_prizes])?;
+    }
+    let mut first_prizes = config.clone();
+    pool.apply(&mut first_prizes);
+    // The prizes still come from a deck of the same size, which now holds only these prizes
+    first_prizes.deck_size = config.deck_size;
+    first_prizes.validate()?;
+
+    Ok(first_prizes)
+}
+
+/// For each roll index, count how often each of the `prizes` prizes was first earned on that roll
+fn record_first_earned(first_earned: &mut Vec<Vec<usize>>, prizes: usize, sim_res: &[RollResult]) {
+    if first_earned.len() < sim_res.len() {
+        first_earned.resize(sim_res.len(), vec![0; prizes]);
+    }
+
+    for (roll_idx, prize_idx) in new_prize_rolls(sim_res) {
+        first_earned[roll_idx][prize_idx] += 1;
+    }
+}
+
+/// Count which position each prize was collected in
+fn record_collection_order(collection_order: &mut [Vec<usize>], sim_res: &[RollResult]) {
+    for (position, (_, prize_idx)) in new_prize_rolls(sim_res).enumerate() {
+        collection_order[prize_idx][position] += 1;
+    }
+}
+
+/// The last prize a run collected for the first time, if it rolled anything
+fn last_new_prize(sim_res: &[RollResult]) -> Option<usize> {
+    new_prize_rolls(sim_res)
+        .last()
+        .map(|(_, prize_idx)| prize_idx)
+}
+
+/// How many of the rolls in `sim_res` were duplicates. Every prize needs its copies, so only the
+/// rolls beyond those count
+#[cfg(feature = "sqlite")]
+fn duplicate_rolls(config: &SimConfig, sim_res: &[RollResult]) -> usize {
+    let mut prize_counts = vec![0; config.prizes];
+    for roll_result in sim_res {
+        prize_counts[usize::from(roll_result)] += 1;
+    }
+    let needed_rolls: usize = prize_counts
+        .iter()
+        .zip(&config.copies)
+        .map(|(&count, &copies)| count.min(copies))
+        .sum();
+
+    sim_res.len() - needed_rolls
+}
+
+/// Write one run's rolls as a line of space-separated prize indices
+pub fn write_roll_sequence<W: Write + ?Sized>(
+    writer: &mut W,
+    rolls: &[RollResult],
+) -> io::Result<()> {
+    for (roll_idx, roll_result) in rolls.iter().enumerate() {
+        if roll_idx > 0 {
+            write!(writer, " ")?;
+        }
+        write!(writer, "{}", usize::from(roll_result))?;
+    }
+    writeln!(writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::SmallRng, SeedableRng};
+
+    use super::*;
+    use crate::{exact_pmf, run_sim, PITY_THRESHOLD, TEST_SEED};
+
+    fn simulate_runs(simulator: &Simulator, runs: usize, seed: u64) -> Aggregate {
+        simulator
+            .simulate(
+                &mut SmallRng::seed_from_u64(seed),
+                &SimulateOptions::new(RunLimit::Runs(runs)),
+                None,
+                &AtomicBool::new(false),
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn prize_pool_checks_its_prizes() {
+        assert!(PrizePool::new(0).is_err());
+        assert_eq!(PrizePool::new(3).unwrap().prizes(), 3);
+        assert!(PrizePool::new(3).unwrap().with_weights(&[1., 2.]).is_err());
+        assert!(PrizePool::new(3).unwrap().with_copies(&[1, 0, 1]).is_err());
+        assert!(PrizePool::new(3).unwrap().with_copies(&[1, 2]).is_err());
+    }
+
+    #[test]
+    fn default_pool_simulates_the_default_rules() {
+        let simulator = Simulator::new(&PrizePool::default());
+        assert!(simulator.config().weights.is_none());
+        for seed in 0..20 {
+            assert_eq!(
+                simulator.run(&mut SmallRng::seed_from_u64(seed)).rolls,
+                run_sim(&SimConfig::default(), &mut SmallRng::seed_from_u64(seed))
+            );
+        }
+    }
+
+    #[test]
+    fn small_pool_only_rolls_its_own_prizes() {
+        let pool = PrizePool::new(5).unwrap();
+        let simulator = Simulator::new(&pool);
+        let mut rng = SmallRng::seed_from_u64(TEST_SEED);
+        for _ in 0..1000 {
+            let run = simulator.run(&mut rng);
+            assert!(run.finished);
+            assert!(run.rolls.len() >= 5);
+            assert!(run
+                .rolls
+                .iter()
+                .all(|roll_result| usize::from(roll_result) < 5));
+        }
+    }
+
+    #[test]
+    fn pool_larger_than_the_default_needs_every_prize() {
+        let prizes = 2 * NUM_PRIZES;
+        let simulator = Simulator::new(&PrizePool::new(prizes).unwrap());
+        let aggregate = simulator
+            .simulate(
+                &mut SmallRng::seed_from_u64(TEST_SEED),
+                &SimulateOptions {
+                    collection_order: true,
+                    ..SimulateOptions::new(RunLimit::Runs(20_000))
+                },
+                None,
+                &AtomicBool::new(false),
+            )
+            .unwrap();
+
+        // Every run collected all of the prizes in some order
+        for counts in &aggregate.collection_order {
+            assert_eq!(counts.len(), prizes);
+            assert_eq!(counts.iter().sum::<usize>(), aggregate.completed);
+        }
+        let summary = aggregate.summary(&[]).unwrap();
+        assert!(summary.min >= prizes);
+        let exact_mean =
+            crate::exact_mean_rolls(prizes, prizes, Some(simulator.config().pity_threshold));
+        assert!(
+            (summary.mean - exact_mean).abs() < 0.2,
+            "mean {}, expected {}",
+            summary.mean,
+            exact_mean
+        );
+    }
+
+    #[test]
+    fn copies_raise_the_fewest_rolls() {
+        let pool = PrizePool::new(4)
+            .unwrap()
+            .with_copies(&[1, 2, 3, 1])
+            .unwrap();
+        let simulator = Simulator::new(&pool);
+        assert_eq!(simulator.config().min_rolls(), 7);
+        let summary = simulate_runs(&simulator, 2000, TEST_SEED)
+            .summary(&[])
+            .unwrap();
+        assert!(summary.min >= 7);
+    }
+
+    #[test]
+    fn simulate_completes_every_requested_run() {
+        let aggregate = simulate_runs(&Simulator::default(), 1234, TEST_SEED);
+        assert_eq!(aggregate.completed, 1234);
+        assert_eq!(aggregate.abandoned, 0);
+        assert_eq!(aggregate.hist_data.values().sum::<usize>(), 1234);
+        assert_eq!(
+            aggregate.total_rolls,
+            aggregate
+                .hist_data
+                .iter()
+                .map(|(rolls, count)| rolls * count)
+                .sum::<usize>()
+        );
+    }
+
+    #[test]
+    fn simulate_in_parallel_shares_out_the_runs() {
+        let aggregate = Simulator::default()
+            .simulate_in_parallel(
+                &SimulateOptions::new(RunLimit::Runs(1001)),
+                4,
+                |thread_idx| SmallRng::seed_from_u64(TEST_SEED + thread_idx as u64),
+                &AtomicBool::new(false),
+            )
+            .unwrap();
+        assert_eq!(aggregate.completed, 1001);
+        assert_eq!(aggregate.hist_data.values().sum::<usize>(), 1001);
+    }
+
+    #[test]
+    fn runs_that_all_give_up_have_no_summary() {
+        let simulator = Simulator::default()
+            .with_pity(PityMode::None, 0)
+            .with_give_up(Some(3));
+        let aggregate = simulate_runs(&simulator, 100, TEST_SEED);
+        assert_eq!(aggregate.abandoned, 100);
+        assert!(aggregate.summary(&[]).is_none());
+    }
+
+    #[test]
+    fn roll_sink_gets_one_line_per_finished_run() {
+        let mut sink = Vec::new();
+        let aggregate = Simulator::default()
+            .simulate(
+                &mut SmallRng::seed_from_u64(TEST_SEED),
+                &SimulateOptions {
+                    roll_sequences: true,
+                    ..SimulateOptions::new(RunLimit::Runs(50))
+                },
+                Some(&mut sink),
+                &AtomicBool::new(false),
+            )
+            .unwrap();
+        assert!(aggregate.roll_sequences.is_empty());
+        assert_eq!(String::from_utf8(sink).unwrap().lines().count(), 50);
+    }
+
+    #[test]
+    fn merge_adds_up_both_aggregates() {
+        let simulator = Simulator::default();
+        let mut aggregate = simulate_runs(&simulator, 300, TEST_SEED);
+        let other = simulate_runs(&simulator, 200, TEST_SEED + 1);
+        let total_rolls = aggregate.total_rolls + other.total_rolls;
+        aggregate.merge(other);
+        assert_eq!(aggregate.completed, 500);
+        assert_eq!(aggregate.total_rolls, total_rolls);
+        assert_eq!(aggregate.hist_data.values().sum::<usize>(), 500);
+    }
+
+    #[test]
+    fn collection_order_rows_and_columns_count_every_run() {
+        let aggregate = Simulator::default()
+            .simulate(
+                &mut SmallRng::seed_from_u64(TEST_SEED),
+                &SimulateOptions {
+                    collection_order: true,
+                    ..SimulateOptions::new(RunLimit::Runs(50))
+                },
+                None,
+                &AtomicBool::new(false),
+            )
+            .unwrap();
+
+        // Every run collects every prize exactly once, in exactly one position
+        for counts in &aggregate.collection_order {
+            assert_eq!(counts.iter().sum::<usize>(), 50);
+        }
+        for position in 0..NUM_PRIZES {
+            let column: usize = aggregate
+                .collection_order
+                .iter()
+                .map(|counts| counts[position])
+                .sum();
+            assert_eq!(column, 50);
+        }
+    }
+
+    #[test]
+    fn tight_budget_abandons_the_runs_that_need_more_rolls() {
+        // Pity only starts after the budget runs out, so it never comes into it
+        let give_up = 12;
+        let simulator = Simulator::default().with_give_up(Some(give_up));
+        let runs = 20_000;
+        let aggregate = simulate_runs(&simulator, runs, TEST_SEED);
+        assert_eq!(aggregate.completed, runs);
+        assert_eq!(
+            aggregate.abandoned + aggregate.hist_data.values().sum::<usize>(),
+            runs
+        );
+        assert!(aggregate.hist_data.keys().all(|&rolls| rolls <= give_up));
+
+        // A run is abandoned when it needs more than `give_up` rolls. The standard error over
+        // 20000 runs is at most 0.0035
+        let expected: f64 = 1.
+            - exact_pmf(NUM_PRIZES, NUM_PRIZES, simulator.config().pity_threshold)[..=give_up]
+                .iter()
+                .sum::<f64>();
+        let abandoned = aggregate.abandoned as f64 / runs as f64;
+        assert!(
+            (abandoned - expected).abs() < 0.015,
+            "{} of the runs were abandoned, expected {}",
+            abandoned,
+            expected
+        );
+    }
+
+    #[test]
+    fn last_gap_is_the_longest_on_average() {
+        let aggregate = Simulator::default()
+            .with_pity(PityMode::None, 0)
+            .simulate(
+                &mut SmallRng::seed_from_u64(TEST_SEED),
+                &SimulateOptions {
+                    gaps: true,
+                    ..SimulateOptions::new(RunLimit::Runs(20_000))
+                },
+                None,
+                &AtomicBool::new(false),
+            )
+            .unwrap();
+        assert!(aggregate.gap_counts.iter().all(|&count| count == 20_000));
+        let mean_gaps: Vec<f64> = aggregate
+            .gap_totals
+            .iter()
+            .zip(&aggregate.gap_counts)
+            .map(|(&total, &count)| total as f64 / count as f64)
+            .collect();
+
+        // Going from k to k + 1 prizes takes 8 / (8 - k) rolls on average, so every gap is longer
+        // than the one before and the last one takes 8
+        for pair in mean_gaps.windows(2) {
+            assert!(pair[0] < pair[1], "{:?}", mean_gaps);
+        }
+        let last_gap = *mean_gaps.last().unwrap();
+        assert!(
+            (last_gap - NUM_PRIZES as f64).abs() < 0.2,
+            "{:?}",
+            mean_gaps
+        );
+    }
+
+    #[test]
+    fn independent_streams_have_no_autocorrelation() {
+        let options = SimulateOptions {
+            autocorrelation: true,
+            ..SimulateOptions::new(RunLimit::Runs(20_000))
+        };
+        let aggregate = Simulator::default()
+            .simulate_in_parallel(
+                &options,
+                4,
+                |thread_idx| SmallRng::seed_from_u64(TEST_SEED + thread_idx as u64),
+                &AtomicBool::new(false),
+            )
+            .unwrap();
+        let autocorrelation = &aggregate.autocorrelation;
+        assert_eq!(autocorrelation.count(), 20_000);
+        assert!(
+            autocorrelation.lag1().abs() < autocorrelation.significance_bound(),
+            "lag-1 autocorrelation {} is beyond {}",
+            autocorrelation.lag1(),
+            autocorrelation.significance_bound()
+        );
+
+        // Runs that alternate between short and long are as anticorrelated as it gets
+        let mut alternating = Autocorrelation::default();
+        for run_idx in 0..1000 {
+            alternating.push(if run_idx % 2 == 0 { 10 } else { 30 });
+        }
+        assert!(alternating.lag1() < -0.99);
+    }
+
+    #[test]
+    fn split_by_pity_adds_up_to_every_finished_run() {
+        let aggregate = Simulator::default()
+            .simulate(
+                &mut SmallRng::seed_from_u64(TEST_SEED),
+                &SimulateOptions {
+                    split_by_pity: true,
+                    ..SimulateOptions::new(RunLimit::Runs(5000))
+                },
+                None,
+                &AtomicBool::new(false),
+            )
+            .unwrap();
+
+        let mut combined = aggregate.hist_without_pity.clone();
+        for (&rolls, &count) in &aggregate.hist_with_pity {
+            *combined.entry(rolls).or_insert(0) += count;
+        }
+        assert_eq!(combined, aggregate.hist_data);
+        // Pity only grants prizes once the threshold is reached
+        let pity_threshold = Simulator::default().config().pity_threshold;
+        assert!(aggregate
+            .hist_with_pity
+            .keys()
+            .all(|&rolls| rolls > pity_threshold));
+        assert!(aggregate
+            .hist_without_pity
+            .keys()
+            .all(|&rolls| rolls <= pity_threshold));
+        assert!(!aggregate.hist_with_pity.is_empty() && !aggregate.hist_without_pity.is_empty());
+    }
+
+    #[test]
+    fn antithetic_pairs_narrow_the_confidence_interval() {
+        let runs = 20_000;
+        let independent = simulate_runs(&Simulator::default(), runs, TEST_SEED);
+        assert_eq!(independent.pair_standard_error(), None);
+        let independent_error = independent.summary(&[]).unwrap().standard_error();
+
+        let paired = Simulator::default()
+            .simulate(
+                &mut SmallRng::seed_from_u64(TEST_SEED),
+                &SimulateOptions {
+                    variance_reduction: true,
+                    ..SimulateOptions::new(RunLimit::Runs(runs))
+                },
+                None,
+                &AtomicBool::new(false),
+            )
+            .unwrap();
+        assert_eq!(paired.pairs, runs / 2);
+        let paired_error = paired.pair_standard_error().unwrap();
+        // About 12% less on the default rules
+        assert!(
+            paired_error < 0.95 * independent_error,
+            "{} with pairs, {} without",
+            paired_error,
+            independent_error
+        );
+    }
+
+    #[test]
+    fn every_prize_is_equally_likely_to_be_last_under_uniform_weights() {
+        let runs = 40_000;
+        let aggregate = Simulator::default()
+            .with_pity(PityMode::None, 0)
+            .simulate(
+                &mut SmallRng::seed_from_u64(TEST_SEED),
+                &SimulateOptions {
+                    last_earned: true,
+                    ..SimulateOptions::new(RunLimit::Runs(runs))
+                },
+                None,
+                &AtomicBool::new(false),
+            )
+            .unwrap();
+        assert_eq!(aggregate.last_earned.iter().sum::<usize>(), runs);
+
+        // The standard error of each share is about 0.0017
+        for (prize_idx, &last) in aggregate.last_earned.iter().enumerate() {
+            let share = last as f64 / runs as f64;
+            assert!(
+                (share - 1. / NUM_PRIZES as f64).abs() < 0.008,
+                "prize {} was last in {} of the runs",
+                prize_idx,
+                share
+            );
+        }
+    }
+
+    #[test]
+    fn condition_on_splits_a_seeded_dataset_by_the_early_event() {
+        let runs = 20_000;
+        let aggregate = Simulator::default()
+            .simulate(
+                &mut SmallRng::seed_from_u64(TEST_SEED),
+                &SimulateOptions {
+                    condition_on: Some(EarlyEvent::DuplicateWithin(2)),
+                    ..SimulateOptions::new(RunLimit::Runs(runs))
+                },
+                None,
+                &AtomicBool::new(false),
+            )
+            .unwrap();
+
+        let mut combined = aggregate.hist_without_event.clone();
+        for (&rolls, &count) in &aggregate.hist_with_event {
+            *combined.entry(rolls).or_insert(0) += count;
+        }
+        assert_eq!(combined, aggregate.hist_data);
+
+        // The second roll repeats the first one 1 time in 8, and wastes a roll when it does
+        let with_event = analyze_histogram(&aggregate.hist_with_event, &[]);
+        let without_event = analyze_histogram(&aggregate.hist_without_event, &[]);
+        let share = with_event.runs as f64 / runs as f64;
+        assert!((share - 1. / NUM_PRIZES as f64).abs() < 0.01, "{}", share);
+        assert!(with_event.mean > without_event.mean);
+        assert!(with_event.min > NUM_PRIZES);
+    }
+
+    #[test]
+    fn early_events_look_at_the_first_rolls_only() {
+        let rolls: Vec<RollResult> = [0, 1, 0, 2, 3, 4, 5, 6, 7]
+            .into_iter()
+            .map(|prize_idx: usize| RollResult::from(prize_idx))
+            .collect();
+        assert!(!EarlyEvent::DuplicateWithin(2).happened(&rolls));
+        assert!(EarlyEvent::DuplicateWithin(3).happened(&rolls));
+        assert!(EarlyEvent::PrizesWithin {
+            rolls: 4,
+            prizes: 3
+        }
+        .happened(&rolls));
+        assert!(!EarlyEvent::PrizesWithin {
+            rolls: 3,
+            prizes: 3
+        }
+        .happened(&rolls));
+    }
+
+    #[test]
+    fn compared_none_mode_is_a_plain_run_without_pity() {
+        let simulator = Simulator::default();
+        let options = SimulateOptions::new(RunLimit::Runs(200));
+        let histograms = simulator
+            .pity_mode_histograms(
+                &[PityMode::Hard, PityMode::None],
+                &options,
+                || SmallRng::seed_from_u64(TEST_SEED),
+                &AtomicBool::new(false),
+            )
+            .unwrap();
+        assert_eq!(histograms.len(), 2);
+        assert_eq!(histograms[1].0, "pity-mode=none");
+
+        let no_pity = simulate_runs(
+            &simulator.clone().with_pity(PityMode::None, PITY_THRESHOLD),
+            200,
+            TEST_SEED,
+        );
+        assert_eq!(histograms[1].1, no_pity.hist_data);
+        assert_ne!(histograms[0].1, no_pity.hist_data);
+    }
+
+    #[test]
+    fn prize_counts_keep_the_rules_of_their_prizes() {
+        let pool = PrizePool::new(3).unwrap().with_copies(&[3, 1, 1]).unwrap();
+        let mut config = Simulator::new(&pool).config().clone();
+        config.streakiness = 0.5;
+        let mut rng = SmallRng::seed_from_u64(TEST_SEED);
+        let scenarios = Simulator::from(config.clone())
+            .simulate_prize_counts(200, &mut rng, &AtomicBool::new(false))
+            .unwrap();
+        assert_eq!(
+            scenarios
+                .iter()
+                .map(|(num_prizes, _)| *num_prizes)
+                .collect::<Vec<_>>(),
+            [1, 2, 3]
+        );
+        // With only the first prize, every run rolls it the 3 times it has to be rolled
+        assert_eq!((scenarios[0].1.min, scenarios[0].1.max), (3, 3));
+        assert!(scenarios[2].1.min >= 5);
+
+        // Giving up before 3 rolls leaves nothing of the first count to summarize
+        let simulator = Simulator::from(config).with_give_up(Some(2));
+        assert!(matches!(
+            simulator.simulate_prize_counts(200, &mut rng, &AtomicBool::new(false)),
+            Err(CompareError::NoneFinished(_))
+        ));
+    }
+
+    #[test]
+    fn solved_pity_threshold_is_the_closest_to_the_target() {
+        let simulator = Simulator::default();
+        let new_rng = || SmallRng::seed_from_u64(TEST_SEED);
+        let target_mean = Simulator::default()
+            .with_pity(PityMode::Hard, 15)
+            .mean_rolls(2000, &mut new_rng(), &AtomicBool::new(false))
+            .unwrap();
+        let solution = simulator
+            .solve_pity_for_mean(2000, target_mean, new_rng, &AtomicBool::new(false))
+            .unwrap()
+            .unwrap();
+        assert!(solution.reaches_target);
+        assert_eq!(solution.pity_threshold, 15);
+        assert_eq!(solution.mean, target_mean);
+
+        // Even pity on every roll takes a roll per prize
+        let solution = simulator
+            .solve_pity_for_mean(2000, 1., new_rng, &AtomicBool::new(false))
+            .unwrap()
+            .unwrap();
+        assert!(!solution.reaches_target);
+        assert_eq!(solution.pity_threshold, 0);
+
+        let no_pity = simulator.with_pity(PityMode::None, PITY_THRESHOLD);
+        assert!(matches!(
+            no_pity.solve_pity_for_mean(2000, 20., new_rng, &AtomicBool::new(false)),
+            Err(CompareError::Config(_))
+        ));
+    }
+
+    #[test]
+    fn session_stats_count_every_session() {
+        let simulator = Simulator::default();
+        let mut rng = SmallRng::seed_from_u64(TEST_SEED);
+        let session_stats = simulator.session_stats(5, 1000, &mut rng, &AtomicBool::new(false));
+        assert_eq!(session_stats.sessions(), 1000);
+        // 5 pulls can't earn more than 5 new prizes, and always earn at least 1
+        assert_eq!(session_stats.new_prize_counts.len(), NUM_PRIZES + 1);
+        assert_eq!(session_stats.new_prize_counts[0], 0);
+        assert!(session_stats.new_prize_counts[6..]
+            .iter()
+            .all(|&count| count == 0));
+        let mean_new_prizes = session_stats.mean_new_prizes().unwrap();
+        assert!((1. ..=5.).contains(&mean_new_prizes));
+    }
+}
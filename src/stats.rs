@@ -0,0 +1,500 @@
+//! Summary statistics of simulated runs: the moments, percentiles, cumulative distribution and
+//! hazard of the number of rolls, and the checks that runs behave the way they should
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::Serialize;
+
+/// The Gini coefficient of `values`, from 0 when they're all equal up to almost 1 when one of them
+/// makes up the whole total. Returns 0 when there's nothing to compare
+pub fn gini_coefficient(values: &[f64]) -> f64 {
+    let total: f64 = values.iter().sum();
+    if values.len() < 2 || total <= 0. {
+        return 0.;
+    }
+    // Half the average absolute difference between every pair, relative to the average
+    let sum_differences: f64 = values
+        .iter()
+        .map(|a| values.iter().map(|b| (a - b).abs()).sum::<f64>())
+        .sum();
+    sum_differences / (2. * values.len() as f64 * total)
+}
+
+/// Running sums for the lag-1 autocorrelation of the numbers of rolls of consecutive runs
+///
+/// Runs are supposed to be independent, so the autocorrelation should be close to 0. Anything
+/// well beyond [`significance_bound`](Self::significance_bound) points to runs that share state,
+/// like generators seeded from each other.
+#[derive(Clone, Debug, Default)]
+pub struct Autocorrelation {
+    count: usize,
+    sum: f64,
+    sum_squares: f64,
+    /// The sum of the products of every number of rolls with the next one
+    sum_lag_products: f64,
+    first: f64,
+    last: f64,
+}
+
+impl Autocorrelation {
+    /// Add the number of rolls of the next run
+    pub fn push(&mut self, rolls: usize) {
+        let rolls = rolls as f64;
+        if self.count == 0 {
+            self.first = rolls;
+        } else {
+            self.sum_lag_products += self.last * rolls;
+        }
+        self.count += 1;
+        self.sum += rolls;
+        self.sum_squares += rolls * rolls;
+        self.last = rolls;
+    }
+
+    /// Add the runs of `other` as if they came after the runs pushed so far
+    pub fn append(&mut self, other: &Autocorrelation) {
+        if other.count == 0 {
+            return;
+        }
+        if self.count == 0 {
+            self.first = other.first;
+        } else {
+            self.sum_lag_products += self.last * other.first;
+        }
+        self.count += other.count;
+        self.sum += other.sum;
+        self.sum_squares += other.sum_squares;
+        self.sum_lag_products += other.sum_lag_products;
+        self.last = other.last;
+    }
+
+    /// The number of runs pushed
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// The lag-1 autocorrelation, or NaN when there are fewer than 2 runs or they all took the
+    /// same number of rolls
+    pub fn lag1(&self) -> f64 {
+        let n = self.count as f64;
+        let mean = self.sum / n;
+        // Expand the sum of (x[t] - mean) * (x[t + 1] - mean) over t = 1..n - 1
+        let covariance = self.sum_lag_products - mean * (2. * self.sum - self.first - self.last)
+            + (n - 1.) * mean * mean;
+        let variance = self.sum_squares - n * mean * mean;
+        covariance / variance
+    }
+
+    /// How far from 0 the lag-1 autocorrelation of independent runs gets at most 99% of the time
+    pub fn significance_bound(&self) -> f64 {
+        2.576 / (self.count as f64).sqrt()
+    }
+}
+
+/// Outcome of a Kolmogorov-Smirnov test of simulated results against an exact distribution
+#[derive(Debug)]
+pub struct KsTest {
+    /// The largest distance between the simulated and exact CDFs
+    pub statistic: f64,
+    /// The largest distance allowed at the chosen significance level
+    pub critical_value: f64,
+}
+
+impl KsTest {
+    pub fn passed(&self) -> bool {
+        self.statistic <= self.critical_value
+    }
+}
+
+/// Compare a histogram of roll counts against `pmf` with a Kolmogorov-Smirnov test at
+/// significance level `alpha`
+///
+/// The test is conservative for discrete distributions like this one, so it rejects less often
+/// than `alpha` when the simulation is correct.
+pub fn ks_test(hist_data: &HashMap<usize, usize>, pmf: &[f64], alpha: f64) -> KsTest {
+    let runs = hist_data.values().sum::<usize>() as f64;
+    let max_rolls = hist_data.keys().copied().max().unwrap_or(0).max(pmf.len());
+
+    let mut statistic: f64 = 0.;
+    let (mut simulated_cdf, mut exact_cdf) = (0., 0.);
+    for rolls in 0..=max_rolls {
+        simulated_cdf += *hist_data.get(&rolls).unwrap_or(&0) as f64 / runs;
+        exact_cdf += pmf.get(rolls).unwrap_or(&0.);
+        statistic = statistic.max((simulated_cdf - exact_cdf).abs());
+    }
+
+    KsTest {
+        statistic,
+        critical_value: (-(alpha / 2.).ln() / 2.).sqrt() / runs.sqrt(),
+    }
+}
+
+/// Summary statistics of how many rolls a set of runs took to earn every prize
+#[derive(Debug, Clone, Serialize)]
+pub struct StatsSummary {
+    pub runs: usize,
+    pub mean: f64,
+    /// Sample variance, i.e. with Bessel's correction
+    pub variance: f64,
+    pub std_dev: f64,
+    /// How lopsided the distribution is; positive when the long tail is on the high side
+    pub skewness: f64,
+    /// Kurtosis minus 3, so a normal distribution has an excess kurtosis of 0
+    pub excess_kurtosis: f64,
+    pub min: usize,
+    pub max: usize,
+    pub median: usize,
+    /// The requested percentiles, in the order they were requested
+    pub percentiles: Vec<Percentile>,
+    /// The most common number of rolls, the smallest one if there is a tie
+    pub mode: usize,
+    /// How many runs took each number of rolls
+    pub histogram: BTreeMap<usize, usize>,
+}
+
+impl StatsSummary {
+    /// The standard error of the mean, i.e. how much the mean is expected to move between repeated
+    /// simulations of the same size
+    pub fn standard_error(&self) -> f64 {
+        self.std_dev / (self.runs as f64).sqrt()
+    }
+
+    /// The 95% confidence interval of the mean as `(low, high)`, using the normal approximation
+    pub fn mean_confidence_interval(&self) -> (f64, f64) {
+        let half_width = 1.96 * self.standard_error();
+        (self.mean - half_width, self.mean + half_width)
+    }
+
+    /// The smallest number of rolls that at least `percentile` percent of runs finished within,
+    /// whether or not it was one of the percentiles asked for
+    pub fn percentile(&self, percentile: f64) -> usize {
+        nearest_rank(&self.histogram, self.runs, percentile)
+    }
+
+    /// The fraction of runs that finished within `rolls` rolls. With `inclusive`, runs that took
+    /// exactly `rolls` rolls count as within, i.e. P(rolls ≤ N) rather than P(rolls < N)
+    pub fn probability_within(&self, rolls: usize, inclusive: bool) -> f64 {
+        let within: usize = if inclusive {
+            self.histogram
+                .range(..=rolls)
+                .map(|(_, &count)| count)
+                .sum()
+        } else {
+            self.histogram.range(..rolls).map(|(_, &count)| count).sum()
+        };
+        within as f64 / self.runs as f64
+    }
+
+    /// The discrete hazard of every number of rolls from the fewest to the most any run took: the
+    /// chance that a run finishes on that roll given that it hadn't finished before it
+    ///
+    /// Every number of rolls up to the most any run took still has runs left going into it, so the
+    /// hazard is defined all the way and is 1 at the end.
+    pub fn hazard(&self) -> Vec<(usize, f64)> {
+        let mut finished_before = 0;
+        (self.min..=self.max)
+            .map(|rolls| {
+                let count = self.histogram.get(&rolls).copied().unwrap_or(0);
+                let at_risk = self.runs - finished_before;
+                finished_before += count;
+                (rolls, count as f64 / at_risk as f64)
+            })
+            .collect()
+    }
+
+    /// The cumulative distribution: for every number of rolls from the fewest to the most any run
+    /// took, the fraction of runs that finished within that many rolls
+    pub fn cdf(&self) -> Vec<(usize, f64)> {
+        let mut finished = 0;
+        (self.min..=self.max)
+            .map(|rolls| {
+                finished += self.histogram.get(&rolls).copied().unwrap_or(0);
+                (rolls, finished as f64 / self.runs as f64)
+            })
+            .collect()
+    }
+}
+
+/// Nearest-rank percentile: the first number of rolls whose cumulative count reaches the rank
+fn nearest_rank(histogram: &BTreeMap<usize, usize>, runs: usize, percentile: f64) -> usize {
+    let rank = ((percentile / 100. * runs as f64).ceil() as usize).clamp(1, runs);
+    let mut cumulative = 0;
+    histogram
+        .iter()
+        .find_map(|(&rolls, &count)| {
+            cumulative += count;
+            (cumulative >= rank).then_some(rolls)
+        })
+        .unwrap() // okay to unwrap because the cumulative count ends at runs
+}
+
+/// The smallest number of rolls that at least `percentile` percent of runs finished within
+#[derive(Debug, Clone, Serialize)]
+pub struct Percentile {
+    pub percentile: f64,
+    pub rolls: usize,
+}
+
+/// Compute the summary statistics of `lengths`, the number of rolls each run took
+///
+/// # Panics
+///
+/// Panics if `lengths` is empty.
+pub fn analyze(lengths: &[usize], percentiles: &[f64]) -> StatsSummary {
+    let mut hist_data = HashMap::new();
+    for &rolls in lengths {
+        *hist_data.entry(rolls).or_insert(0) += 1;
+    }
+
+    analyze_histogram(&hist_data, percentiles)
+}
+
+/// Compute the summary statistics of a histogram mapping numbers of rolls to how many runs took
+/// that many rolls
+///
+/// # Panics
+///
+/// Panics if the histogram has no runs in it.
+pub fn analyze_histogram(hist_data: &HashMap<usize, usize>, percentiles: &[f64]) -> StatsSummary {
+    let histogram: BTreeMap<usize, usize> = hist_data
+        .iter()
+        .filter(|(_, &count)| count > 0)
+        .map(|(&rolls, &count)| (rolls, count))
+        .collect();
+    let runs: usize = histogram.values().sum();
+    assert!(runs > 0, "can't analyze an empty set of runs");
+
+    let mean = histogram
+        .iter()
+        .map(|(&rolls, &count)| (rolls * count) as f64)
+        .sum::<f64>()
+        / runs as f64;
+    let variance = histogram
+        .iter()
+        .map(|(&rolls, &count)| (rolls as f64 - mean).powi(2) * count as f64)
+        .sum::<f64>()
+        / (runs - 1).max(1) as f64;
+
+    // Skewness and kurtosis use the population central moments. A distribution with no spread has
+    // neither, so report 0 rather than dividing by zero
+    let central_moment = |power: i32| {
+        histogram
+            .iter()
+            .map(|(&rolls, &count)| (rolls as f64 - mean).powi(power) * count as f64)
+            .sum::<f64>()
+            / runs as f64
+    };
+    let second_moment = central_moment(2);
+    let (skewness, excess_kurtosis) = if second_moment > 0. {
+        (
+            central_moment(3) / second_moment.powf(1.5),
+            central_moment(4) / second_moment.powi(2) - 3.,
+        )
+    } else {
+        (0., 0.)
+    };
+
+    let percentile = |percentile: f64| nearest_rank(&histogram, runs, percentile);
+
+    StatsSummary {
+        runs,
+        mean,
+        variance,
+        std_dev: variance.sqrt(),
+        skewness,
+        excess_kurtosis,
+        min: *histogram.keys().next().unwrap(),
+        max: *histogram.keys().next_back().unwrap(),
+        median: percentile(50.),
+        percentiles: percentiles
+            .iter()
+            .map(|&p| Percentile {
+                percentile: p,
+                rolls: percentile(p),
+            })
+            .collect(),
+        mode: histogram
+            .iter()
+            .rev()
+            .max_by_key(|(_, &count)| count)
+            .map(|(&rolls, _)| rolls)
+            .unwrap(),
+        histogram,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::SmallRng, SeedableRng};
+
+    use super::*;
+    use crate::{exact_pmf, run_sim, SimConfig, NUM_PRIZES, TEST_SEED};
+
+    /// A histogram from a list of `(rolls, runs)` pairs
+    fn histogram(spec: &[(usize, usize)]) -> HashMap<usize, usize> {
+        spec.iter().copied().collect()
+    }
+
+    #[test]
+    fn seeded_simulation_passes_the_ks_test() {
+        let config = SimConfig::default();
+        let mut rng = SmallRng::seed_from_u64(TEST_SEED);
+        let mut hist_data = HashMap::new();
+        for _ in 0..50_000 {
+            *hist_data
+                .entry(run_sim(&config, &mut rng).len())
+                .or_insert(0) += 1;
+        }
+
+        let test = ks_test(
+            &hist_data,
+            &exact_pmf(NUM_PRIZES, NUM_PRIZES, config.pity_threshold),
+            0.01,
+        );
+        assert!(test.passed(), "{:?}", test);
+    }
+
+    #[test]
+    fn analyze_summarizes_a_hand_built_dataset() {
+        let summary = analyze(&[9, 12, 8, 10, 9], &[80., 100.]);
+
+        assert_eq!(summary.runs, 5);
+        assert!((summary.mean - 9.6).abs() < 1e-12);
+        // The squared deviations add up to 9.2, over 4 degrees of freedom
+        assert!((summary.variance - 2.3).abs() < 1e-12);
+        assert!((summary.std_dev - 2.3f64.sqrt()).abs() < 1e-12);
+        assert_eq!((summary.min, summary.median, summary.max), (8, 9, 12));
+        assert_eq!(summary.mode, 9);
+        assert_eq!(summary.percentiles[0].rolls, 10);
+        assert_eq!(summary.percentiles[1].rolls, 12);
+        assert_eq!(
+            summary.histogram,
+            BTreeMap::from([(8, 1), (9, 2), (10, 1), (12, 1)])
+        );
+    }
+
+    #[test]
+    fn skewness_and_kurtosis_of_known_datasets() {
+        let close = |actual: f64, expected: f64| (actual - expected).abs() < 1e-12;
+
+        // Symmetric around 2 with all of the weight 1 away, the flattest a distribution can be
+        let two_point = analyze(&[1, 1, 3, 3], &[]);
+        assert!(close(two_point.skewness, 0.));
+        assert!(close(two_point.excess_kurtosis, -2.));
+
+        // Three runs at 1 and one outlier at 4: a long right tail
+        let skewed = analyze(&[1, 1, 1, 4], &[]);
+        assert!(
+            close(skewed.skewness, 2. / 3f64.sqrt()),
+            "{}",
+            skewed.skewness
+        );
+        assert!(
+            close(skewed.excess_kurtosis, -2. / 3.),
+            "{}",
+            skewed.excess_kurtosis
+        );
+
+        // Without any spread both are reported as 0
+        let constant = analyze(&[5, 5, 5], &[]);
+        assert_eq!((constant.skewness, constant.excess_kurtosis), (0., 0.));
+    }
+
+    #[test]
+    fn inclusive_within_counts_the_runs_on_the_threshold() {
+        let summary = analyze(&[8, 9, 10, 10, 12], &[]);
+
+        assert_eq!(summary.probability_within(10, true), 0.8);
+        assert_eq!(summary.probability_within(10, false), 0.4);
+        // They only differ when some run took exactly that many rolls
+        assert_eq!(summary.probability_within(11, true), 0.8);
+        assert_eq!(summary.probability_within(11, false), 0.8);
+        assert_eq!(summary.probability_within(12, true), 1.);
+        assert_eq!(summary.probability_within(8, false), 0.);
+    }
+
+    #[test]
+    fn analyze_histogram_percentiles_use_the_nearest_rank() {
+        let summary = analyze_histogram(
+            &histogram(&[(8, 3), (9, 5), (10, 2)]),
+            &[0., 30., 31., 80., 81., 100.],
+        );
+        let rolls: Vec<_> = summary
+            .percentiles
+            .iter()
+            .map(|percentile| percentile.rolls)
+            .collect();
+        assert_eq!(rolls, [8, 8, 9, 9, 10, 10]);
+        assert_eq!(summary.median, 9);
+        assert_eq!(summary.percentile(95.), 10);
+    }
+
+    #[test]
+    fn analyze_histogram_mean_and_mode() {
+        let summary = analyze_histogram(&histogram(&[(8, 3), (9, 5), (10, 2)]), &[]);
+        assert_eq!(summary.runs, 10);
+        assert!((summary.mean - 8.9).abs() < 1e-12);
+        assert_eq!(summary.mode, 9);
+
+        // A tie goes to the fewest rolls, and empty buckets are left out
+        let tied = analyze_histogram(&histogram(&[(12, 4), (9, 4), (10, 1), (30, 0)]), &[]);
+        assert_eq!(tied.mode, 9);
+        assert_eq!(tied.max, 12);
+        assert_eq!(tied.histogram.len(), 3);
+    }
+
+    #[test]
+    fn analyze_histogram_probabilities() {
+        let summary = analyze_histogram(&histogram(&[(8, 3), (9, 5), (10, 2)]), &[]);
+        assert_eq!(summary.probability_within(7, true), 0.);
+        assert_eq!(summary.probability_within(8, true), 0.3);
+        assert_eq!(summary.probability_within(9, true), 0.8);
+        assert_eq!(summary.probability_within(9, false), 0.3);
+        assert_eq!(summary.probability_within(10, true), 1.);
+        assert_eq!(summary.cdf(), [(8, 0.3), (9, 0.8), (10, 1.)]);
+    }
+
+    #[test]
+    fn one_dominant_gap_has_a_high_gini_coefficient() {
+        assert_eq!(gini_coefficient(&[3.; 7]), 0.);
+        assert_eq!(gini_coefficient(&[]), 0.);
+        assert_eq!(gini_coefficient(&[5.]), 0.);
+
+        // Everything in one of n gaps is as unequal as n values get
+        let all_in_one = gini_coefficient(&[0., 0., 0., 0., 0., 0., 8.]);
+        assert!((all_in_one - 6. / 7.).abs() < 1e-12);
+
+        // 6 * 49 * 2 differences over 2 * 7 * 56
+        let dominant = gini_coefficient(&[1., 1., 1., 1., 1., 1., 50.]);
+        assert!((dominant - 0.75).abs() < 1e-12, "{}", dominant);
+        let reordered = gini_coefficient(&[1., 50., 1., 1., 1., 1., 1.]);
+        assert!((dominant - reordered).abs() < 1e-12);
+        // Gaps that grow steadily are far more even
+        let steady = gini_coefficient(&[1., 2., 3., 4., 5., 6., 7.]);
+        assert!((steady - 2. / 7.).abs() < 1e-12, "{}", steady);
+    }
+
+    #[test]
+    fn hazard_is_defined_up_to_the_longest_run() {
+        // The gaps between the roll counts still have runs at risk
+        let summary = analyze_histogram(&histogram(&[(8, 3), (10, 5), (15, 2)]), &[]);
+        let hazard = summary.hazard();
+        assert_eq!(hazard.len(), 8);
+        assert_eq!(hazard[0], (8, 0.3));
+        assert_eq!(hazard[1], (9, 0.));
+        assert_eq!(hazard[2], (10, 5. / 7.));
+        assert!(hazard[3..7].iter().all(|&(_, hazard)| hazard == 0.));
+        assert_eq!(hazard[7], (15, 1.));
+
+        let config = SimConfig::default();
+        let mut rng = SmallRng::seed_from_u64(TEST_SEED);
+        let lengths: Vec<usize> = (0..5000)
+            .map(|_| run_sim(&config, &mut rng).len())
+            .collect();
+        let hazard = analyze(&lengths, &[]).hazard();
+        assert!(hazard
+            .iter()
+            .all(|&(_, hazard)| hazard.is_finite() && (0. ..=1.).contains(&hazard)));
+        assert_eq!(hazard.last().unwrap().1, 1.);
+    }
+}